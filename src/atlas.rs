@@ -0,0 +1,36 @@
+//! Laying sprites out in OBJ VRAM for 2D mapping, where it's a grid of 32x32 tile slots and
+//! each sprite's tiles must form a rectangle in it.
+
+/// The width and height of OBJ VRAM, in 32 byte tile slots.
+pub const GRID_SIZE: usize = 32;
+
+/// Finds a position in the grid for each `(width, height)` block, in slots, returning the
+/// index of the first block that doesn't fit if they don't all.
+///
+/// Places the tallest blocks first, each at the first free position scanning row by row.
+pub fn pack(blocks: &[(usize, usize)]) -> Result<Vec<(u8, u8)>, usize> {
+    let mut order = (0..blocks.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| std::cmp::Reverse((blocks[i].1, blocks[i].0)));
+
+    let mut used = [[false; GRID_SIZE]; GRID_SIZE];
+    let mut positions = vec![(0, 0); blocks.len()];
+    for i in order {
+        let (w, h) = blocks[i];
+        if w > GRID_SIZE || h > GRID_SIZE {
+            return Err(i);
+        }
+        let fits = |x: usize, y: usize| (y..y + h).all(|y| (x..x + w).all(|x| !used[y][x]));
+
+        let (x, y) = (0..=GRID_SIZE - h)
+            .flat_map(|y| (0..=GRID_SIZE - w).map(move |x| (x, y)))
+            .find(|&(x, y)| fits(x, y))
+            .ok_or(i)?;
+
+        for row in &mut used[y..y + h] {
+            row[x..x + w].iter_mut().for_each(|slot| *slot = true);
+        }
+        positions[i] = (x as u8, y as u8);
+    }
+
+    Ok(positions)
+}
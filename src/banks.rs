@@ -0,0 +1,128 @@
+//! Packing sprites into the 16 color palette banks 4bpp tiles index into.
+
+use lodepng::RGB;
+use std::collections::BTreeSet;
+
+/// Colors a bank holds besides the transparent index 0.
+pub const BANK_COLORS: usize = 15;
+
+/// How many partial assignments the exact search tries before settling for the best found.
+const SEARCH_LIMIT: usize = 100_000;
+
+pub type Colors = BTreeSet<RGB<u8>>;
+
+/// Assigns each color set to a bank with room for it, returning the colors of each bank, the
+/// bank of each set, and whether the bank count is known to be the fewest possible. Sets must
+/// have at most `BANK_COLORS` colors.
+///
+/// The `fixed` banks come first, and sets go in them too if they fit.
+///
+/// Starts from a greedy packing, then searches for one with fewer banks, giving up after
+/// `SEARCH_LIMIT` steps on inputs too big to search exhaustively.
+pub fn pack(fixed: &[Colors], sets: &[Colors]) -> (Vec<Colors>, Vec<usize>, bool) {
+    let mut order = (0..sets.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| std::cmp::Reverse(sets[i].len()));
+
+    let (mut banks, mut assignment) = greedy(fixed, sets, &order);
+
+    let all_colors = fixed
+        .iter()
+        .chain(sets)
+        .flatten()
+        .collect::<BTreeSet<_>>()
+        .len();
+    let lower_bound = all_colors
+        .div_ceil(BANK_COLORS)
+        .max(fixed.len())
+        .max(1)
+        .min(fixed.len() + sets.len());
+    if banks.len() <= lower_bound {
+        return (banks, assignment, true);
+    }
+
+    let mut search = Search {
+        sets,
+        order: &order,
+        banks: fixed.to_vec(),
+        assignment: vec![0; sets.len()],
+        best: None,
+        best_len: banks.len(),
+        steps: 0,
+        lower_bound,
+    };
+    search.place(0);
+    let optimal = search.steps <= SEARCH_LIMIT;
+    if let Some((better_banks, better_assignment)) = search.best {
+        banks = better_banks;
+        assignment = better_assignment;
+    }
+    (banks, assignment, optimal)
+}
+
+/// Places the sets with the most colors first, each into the first bank it fits in.
+fn greedy(fixed: &[Colors], sets: &[Colors], order: &[usize]) -> (Vec<Colors>, Vec<usize>) {
+    let mut banks = fixed.to_vec();
+    let mut assignment = vec![0; sets.len()];
+    for &i in order {
+        let set = &sets[i];
+        let fits = banks
+            .iter()
+            .position(|bank| bank.union(set).count() <= BANK_COLORS);
+        let bank = fits.unwrap_or_else(|| {
+            banks.push(Colors::new());
+            banks.len() - 1
+        });
+
+        banks[bank].extend(set);
+        assignment[i] = bank;
+    }
+
+    (banks, assignment)
+}
+
+/// A branch and bound search over which bank each set goes in, for packings with fewer banks
+/// than `best_len`.
+struct Search<'a> {
+    sets: &'a [Colors],
+    order: &'a [usize],
+    banks: Vec<Colors>,
+    assignment: Vec<usize>,
+    best: Option<(Vec<Colors>, Vec<usize>)>,
+    best_len: usize,
+    steps: usize,
+    lower_bound: usize,
+}
+
+impl Search<'_> {
+    fn place(&mut self, n: usize) {
+        self.steps += 1;
+        if self.steps > SEARCH_LIMIT || self.best_len <= self.lower_bound {
+            return;
+        }
+        let Some(&i) = self.order.get(n) else {
+            self.best_len = self.banks.len();
+            self.best = Some((self.banks.clone(), self.assignment.clone()));
+            return;
+        };
+
+        let set = &self.sets[i];
+        for bank in 0..self.banks.len() {
+            if self.banks[bank].union(set).count() > BANK_COLORS {
+                continue;
+            }
+            let before = self.banks[bank].clone();
+            self.banks[bank].extend(set);
+            self.assignment[i] = bank;
+            self.place(n + 1);
+            self.banks[bank] = before;
+        }
+
+        // Opening a bank only helps if it still leaves room to beat the best.
+        if self.banks.len() + 1 < self.best_len {
+            self.banks.push(set.clone());
+            self.assignment[i] = self.banks.len() - 1;
+            self.place(n + 1);
+            self.banks.pop();
+        }
+    }
+}
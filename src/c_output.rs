@@ -0,0 +1,109 @@
+use crate::{CompiledSprites, Options, PaletteEntry, Result};
+
+use std::{fmt::Write, fs, path::Path};
+
+impl CompiledSprites {
+    /// Writes the sprites as a grit-style `<path>.h` and `<path>.c` pair.
+    pub(crate) fn write_c_files(&self, path: &Path, options: &Options) -> Result<()> {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| format!("Invalid C output path {}", path.display()))?;
+
+        let mut header = String::new();
+        let mut source = String::new();
+        self.write_c_to(name, options, &mut header, &mut source)?;
+
+        fs::write(format!("{}.h", path.display()), header)?;
+        fs::write(format!("{}.c", path.display()), source)?;
+        Ok(())
+    }
+
+    pub(crate) fn write_c_to(
+        &self,
+        name: &str,
+        options: &Options,
+        mut header: impl Write,
+        mut source: impl Write,
+    ) -> Result<()> {
+        let guard = format!("{}_H", name.to_uppercase());
+        writeln!(header, "#ifndef {0}\n#define {0}\n", guard)?;
+        writeln!(source, "#include \"{}.h\"\n", name)?;
+
+        let palette = self
+            .palette_iter()
+            .map(|(_, entry)| match entry {
+                PaletteEntry::Color(_) => format!("0x{:04X}", entry.bgr555(options)),
+                _ => format!("0x{:04X} /* {} */", entry.bgr555(options), entry.describe()),
+            })
+            .collect::<Vec<_>>();
+        writeln!(header, "#define {}PalBase {}", name, self.palette_base)?;
+        writeln!(header, "#define {}PalLen {}", name, palette.len() * 2)?;
+        writeln!(
+            header,
+            "extern const unsigned short {}Pal[{}];\n",
+            name,
+            palette.len()
+        )?;
+        write_c_array(
+            &mut source,
+            "unsigned short",
+            &format!("{}Pal", name),
+            &palette,
+            8,
+        )?;
+
+        if !options.palette_only {
+            for (id, tiles) in self.tiles_iter(options) {
+                let raw = tiles.concat();
+                let data = match self.sprites[id].compression {
+                    Some(c) => c.compress(&raw),
+                    None => raw,
+                };
+                let bytes = data
+                    .iter()
+                    .map(|b| format!("0x{:02X}", b))
+                    .collect::<Vec<_>>();
+
+                writeln!(header, "#define {}TilesLen {}", id, bytes.len())?;
+                writeln!(
+                    header,
+                    "extern const unsigned char {}Tiles[{}];\n",
+                    id,
+                    bytes.len()
+                )?;
+                write_c_array(
+                    &mut source,
+                    "unsigned char",
+                    &format!("{}Tiles", id),
+                    &bytes,
+                    16,
+                )?;
+            }
+        }
+
+        writeln!(header, "#endif // {}", guard)?;
+        Ok(())
+    }
+}
+
+fn write_c_array(
+    mut out: impl Write,
+    ty: &str,
+    name: &str,
+    values: &[String],
+    per_line: usize,
+) -> Result<()> {
+    writeln!(
+        out,
+        "const {} {}[{}] __attribute__((aligned(4))) = {{",
+        ty,
+        name,
+        values.len()
+    )?;
+    for line in values.chunks(per_line) {
+        writeln!(out, "    {},", line.join(", "))?;
+    }
+    writeln!(out, "}};\n")?;
+    Ok(())
+}
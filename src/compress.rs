@@ -0,0 +1,130 @@
+//! Compression in the formats the GBA BIOS decompression routines read.
+
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// For `LZ77UnCompWram` / `LZ77UnCompVram`.
+    Lz77,
+    /// For `RLUnCompWram` / `RLUnCompVram`.
+    Rle,
+}
+
+impl Compression {
+    /// Compresses `data`, padded to a multiple of 4 bytes as the BIOS requires.
+    pub fn compress(self, data: &[u8]) -> Vec<u8> {
+        let mut out = match self {
+            Compression::Lz77 => lz77(data),
+            Compression::Rle => rle(data),
+        };
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+        out
+    }
+}
+
+fn header(kind: u8, len: usize) -> Vec<u8> {
+    assert!(len < 1 << 24, "Too much data to compress");
+    let header = (len as u32) << 8 | kind as u32;
+    header.to_le_bytes().to_vec()
+}
+
+const LZ77_MIN_LEN: usize = 3;
+const LZ77_MAX_LEN: usize = 18;
+/// `LZ77UnCompVram` writes a halfword at a time, so a back reference can't point at the
+/// byte immediately before it, which hasn't been written yet.
+const LZ77_MIN_DISP: usize = 2;
+const LZ77_MAX_DISP: usize = 4096;
+
+fn lz77(data: &[u8]) -> Vec<u8> {
+    let mut out = header(0x10, data.len());
+
+    let mut i = 0;
+    while i < data.len() {
+        let flags_at = out.len();
+        out.push(0);
+
+        for bit in (0..8).rev() {
+            if i >= data.len() {
+                break;
+            }
+
+            match longest_match(data, i) {
+                Some((len, disp)) => {
+                    out[flags_at] |= 1 << bit;
+                    let (len, disp) = (len - LZ77_MIN_LEN, disp - 1);
+                    out.push((len << 4 | disp >> 8) as u8);
+                    out.push(disp as u8);
+                    i += len + LZ77_MIN_LEN;
+                }
+                None => {
+                    out.push(data[i]);
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// The longest earlier run of bytes matching those at `i`, as `(length, displacement)`.
+fn longest_match(data: &[u8], i: usize) -> Option<(usize, usize)> {
+    let max_len = LZ77_MAX_LEN.min(data.len() - i);
+    let mut best = None;
+
+    for disp in LZ77_MIN_DISP..=LZ77_MAX_DISP.min(i) {
+        // Matches may run on into the bytes they produce.
+        let len = (0..max_len)
+            .take_while(|&j| data[i - disp + j] == data[i + j])
+            .count();
+        if len >= LZ77_MIN_LEN && best.is_none_or(|(best_len, _)| len > best_len) {
+            best = Some((len, disp));
+        }
+    }
+
+    best
+}
+
+const RLE_MIN_RUN: usize = 3;
+const RLE_MAX_RUN: usize = 130;
+const RLE_MAX_LITERALS: usize = 128;
+
+fn rle(data: &[u8]) -> Vec<u8> {
+    let mut out = header(0x30, data.len());
+    let mut literals: Vec<u8> = Vec::new();
+
+    let flush = |out: &mut Vec<u8>, literals: &mut Vec<u8>| {
+        if !literals.is_empty() {
+            out.push((literals.len() - 1) as u8);
+            out.append(literals);
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let run = data[i..]
+            .iter()
+            .take(RLE_MAX_RUN)
+            .take_while(|&&b| b == data[i])
+            .count();
+
+        if run >= RLE_MIN_RUN {
+            flush(&mut out, &mut literals);
+            out.push(0x80 | (run - RLE_MIN_RUN) as u8);
+            out.push(data[i]);
+            i += run;
+        } else {
+            literals.push(data[i]);
+            if literals.len() == RLE_MAX_LITERALS {
+                flush(&mut out, &mut literals);
+            }
+            i += 1;
+        }
+    }
+    flush(&mut out, &mut literals);
+
+    out
+}
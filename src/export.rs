@@ -0,0 +1,173 @@
+//! Drawing every compiled sprite onto one labelled PNG sheet, to review what the GBA will show.
+
+use crate::{bgr555, CompiledSprites, Options, Result};
+
+use lodepng::{RGB, RGBA};
+use std::path::Path;
+
+/// The widest a row of the sheet gets before the next sprite starts a new one, unless a single
+/// sprite is wider.
+const MAX_ROW_WIDTH: usize = 256;
+/// The space around and between sprites.
+const GAP: usize = 4;
+/// The label's height above each sprite, with a pixel of space below it.
+const LABEL_HEIGHT: usize = 6;
+
+const BACKGROUND: RGBA = RGBA::new(32, 32, 32, 255);
+/// Behind each sprite's own rect, so its transparent pixels show where it ends.
+const SPRITE_BACKGROUND: RGBA = RGBA::new(56, 56, 56, 255);
+const LABEL_COLOR: RGBA = RGBA::new(224, 224, 224, 255);
+
+/// A 3x5 pixel font for sprite names, each row a 3 bit mask with the left pixel highest.
+const GLYPHS: &[(char, [u8; 5])] = &[
+    ('a', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('b', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('c', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('d', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('e', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('f', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('g', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('h', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('i', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('j', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('k', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('l', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('m', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('n', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('o', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('p', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('r', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('s', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('t', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('u', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('v', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('w', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('x', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b110, 0b001, 0b010, 0b100, 0b111]),
+    ('3', [0b110, 0b001, 0b010, 0b001, 0b110]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b110, 0b001, 0b110]),
+    ('6', [0b011, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b110]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    // Anything else.
+    ('?', [0b110, 0b001, 0b010, 0b000, 0b010]),
+];
+
+/// A sprite or bitmap to draw, with all its frames side by side.
+struct Cell<'a> {
+    label: &'a str,
+    width: usize,
+    height: usize,
+    pixel: Box<dyn Fn(usize, usize) -> Option<RGB<u8>> + 'a>,
+}
+
+impl CompiledSprites {
+    /// Writes the sheet drawn by `sheet` to `path` as a PNG.
+    pub(crate) fn export_sheet(&self, path: &Path, options: &Options) -> Result<()> {
+        let (buffer, width, height) = self.sheet(options);
+        lodepng::encode32_file(path, &buffer, width, height)
+            .map_err(|e| format!("Writing {}: {}", path.display(), e))?;
+        Ok(())
+    }
+
+    /// Draws every sprite's frames and every bitmap in rows, each under its name, in the
+    /// BGR555 colors the GBA shows them in. Returns the pixels row by row, and the size.
+    pub(crate) fn sheet(&self, options: &Options) -> (Vec<RGBA>, usize, usize) {
+        let index_color = self.index_color();
+        let gba_color = |c: RGB<u8>| {
+            let c = bgr555(c, options.color_rounding);
+            let channel = |shift: u16| ((c >> shift & 0x1f) << 3) as u8;
+            RGB::new(channel(0), channel(5), channel(10))
+        };
+
+        let mut cells = Vec::new();
+        for (id, sprite) in &self.sprites {
+            let index_color = &index_color;
+            let frame_width = sprite.width / 8 * 8;
+            cells.push(Cell {
+                label: id,
+                width: frame_width * sprite.frames,
+                height: sprite.height / 8 * 8,
+                pixel: Box::new(move |x, y| {
+                    let i = sprite.index_at(x / frame_width, x % frame_width, y);
+                    index_color(sprite, i).map(gba_color)
+                }),
+            });
+        }
+        for (id, bitmap) in &self.bitmaps {
+            cells.push(Cell {
+                label: id,
+                width: bitmap.width,
+                height: bitmap.height,
+                pixel: Box::new(move |x, y| {
+                    let c = bitmap.pixels[y * bitmap.width + x];
+                    let channel = |shift: u16| ((c >> shift & 0x1f) << 3) as u8;
+                    Some(RGB::new(channel(0), channel(5), channel(10)))
+                }),
+            });
+        }
+
+        // Lay the cells out left to right, wrapping into rows.
+        let mut positions = Vec::new();
+        let (mut x, mut y, mut row_height, mut width) = (GAP, GAP, 0, 0);
+        for cell in &cells {
+            let cell_width = cell.width.max(label_width(cell.label));
+            if x > GAP && x + cell_width > MAX_ROW_WIDTH {
+                x = GAP;
+                y += row_height + GAP;
+                row_height = 0;
+            }
+            positions.push((x, y));
+            x += cell_width + GAP;
+            width = width.max(x);
+            row_height = row_height.max(LABEL_HEIGHT + cell.height);
+        }
+        let width = width.max(GAP);
+        let height = y + row_height + GAP;
+
+        let mut buffer = vec![BACKGROUND; width * height];
+        for (cell, (x0, y0)) in cells.iter().zip(positions) {
+            draw_label(&mut buffer, width, (x0, y0), cell.label);
+            let y0 = y0 + LABEL_HEIGHT;
+            for y in 0..cell.height {
+                for x in 0..cell.width {
+                    buffer[(y0 + y) * width + x0 + x] = match (cell.pixel)(x, y) {
+                        Some(c) => RGBA::new(c.r, c.g, c.b, 255),
+                        None => SPRITE_BACKGROUND,
+                    };
+                }
+            }
+        }
+        (buffer, width, height)
+    }
+}
+
+fn label_width(label: &str) -> usize {
+    (label.chars().count() * 4).saturating_sub(1)
+}
+
+fn draw_label(buffer: &mut [RGBA], width: usize, (x0, y0): (usize, usize), label: &str) {
+    for (n, c) in label.chars().enumerate() {
+        let c = c.to_ascii_lowercase();
+        let rows = GLYPHS
+            .iter()
+            .find(|(g, _)| *g == c)
+            .unwrap_or(&GLYPHS[GLYPHS.len() - 1])
+            .1;
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..3 {
+                if row >> (2 - x) & 1 != 0 {
+                    buffer[(y0 + y) * width + x0 + n * 4 + x] = LABEL_COLOR;
+                }
+            }
+        }
+    }
+}
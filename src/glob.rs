@@ -0,0 +1,34 @@
+/// Matches `path` against a glob `pattern` supporting `*`, `**` and `?`.
+///
+/// `*` and `?` don't cross `/`. A pattern without any `/` is matched against just the
+/// file name, so `*.wip.yml` excludes matching files in every directory.
+pub fn matches(pattern: &str, path: &str) -> bool {
+    if !pattern.contains('/') {
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        return matches_from(pattern.as_bytes(), file_name.as_bytes());
+    }
+    matches_from(pattern.as_bytes(), path.as_bytes())
+}
+
+fn matches_from(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            // `**/` can match zero or more whole directories.
+            matches_from(rest, path)
+                || (0..path.len()).any(|i| path[i] == b'/' && matches_from(rest, &path[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| matches_from(rest, &path[i..])),
+        [b'*', rest @ ..] => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != b'/')
+            .any(|i| matches_from(rest, &path[i..])),
+        [b'?', rest @ ..] => match path {
+            [c, path @ ..] if *c != b'/' => matches_from(rest, path),
+            _ => false,
+        },
+        [p, rest @ ..] => match path {
+            [c, path @ ..] if c == p => matches_from(rest, path),
+            _ => false,
+        },
+    }
+}
@@ -1,205 +1,4080 @@
 // #![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(feature = "nightly", feature(proc_macro_diagnostic))]
 
 use proc_macro::TokenStream;
 
+mod atlas;
+mod banks;
+mod c_output;
+mod compress;
+mod export;
+use compress::Compression;
+mod glob;
+mod options;
+mod palette_files;
+mod preview;
+mod region_table;
+use options::{Bpp, ColorRounding, Input, NibbleOrder, Options, Transparency};
+
+#[cfg(test)]
+mod tests;
+
 #[proc_macro]
 pub fn compile(input: TokenStream) -> TokenStream {
-    let in_dir = syn::parse_macro_input!(input as syn::LitStr);
+    let mut input = syn::parse_macro_input!(input as Input);
+
+    // In a workspace, the working directory is the workspace root rather than the crate's
+    // directory, so the prefix depends on where the calling file is.
+    if input.options.include_base.is_none() {
+        let file = proc_macro::Span::call_site().local_file();
+        let cwd = std::env::current_dir();
+        if let (Some(file), Ok(cwd)) = (file, cwd) {
+            input.options.include_base = Some(include_base(&file, &cwd));
+        }
+    }
 
-    match compile_internal(&in_dir.value()) {
+    match compile_internal(&input.dir.value(), &input.options) {
         Ok(s) => s.parse().unwrap(),
         Err(e) => {
-            return proc_macro::TokenStream::from(
-                syn::Error::new(in_dir.span(), e).to_compile_error(),
-            );
+            proc_macro::TokenStream::from(syn::Error::new(input.dir.span(), e).to_compile_error())
         }
     }
 }
 
 use lodepng::*;
 use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
 
-use std::{collections::HashMap, ffi::OsStr, fmt::Write, fs::File, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ffi::OsStr,
+    fmt::Write,
+    fs::File,
+    path::{Path, PathBuf},
+};
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
-fn compile_internal(dir: &str) -> Result<String> {
+/// `(x, y, width, height)` in pixels.
+type Rect = (usize, usize, usize, usize);
+
+/// Setting this environment variable makes the macro print each step of compilation, to help
+/// work out why it produced unexpected output.
+const VERBOSE_VAR: &str = "GBA_SPRITER_VERBOSE";
+
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if std::env::var_os(VERBOSE_VAR).is_some() {
+            eprintln!("gba_spriter: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+fn compile_internal(dir: &str, options: &Options) -> Result<String> {
     let mut out = String::new();
+    let builder = read_dir(dir, options, &mut out)?;
+    if options.variants.is_empty() {
+        emit(builder.compile(options)?, options, &mut out)?;
+        return Ok(out);
+    }
+
+    for (name, variant) in &options.variants {
+        trace!("compiling variant {}", name);
+        let mut body = String::new();
+        emit(builder.clone().compile(variant)?, variant, &mut body)?;
+
+        writeln!(out, "\npub mod {} {{", name)?;
+        for line in body.lines() {
+            if line.is_empty() {
+                writeln!(out)?;
+            } else {
+                writeln!(out, "    {}", line)?;
+            }
+        }
+        writeln!(out, "}}")?;
+    }
+    Ok(out)
+}
+
+/// Reports on `compiled` and writes it to `out`, along with any other outputs `options` asks
+/// for.
+fn emit(compiled: CompiledSprites, options: &Options, mut out: impl Write) -> Result<()> {
+    if options.verbose {
+        for note in &compiled.notes {
+            report("note", note);
+        }
+    }
+    // Asked for by itself, so shown without `verbose`.
+    if let Some(memory_use) = &compiled.memory_use {
+        report("note", memory_use);
+    }
+    if options.deny_warnings && !compiled.warnings.is_empty() {
+        return Err(format!(
+            "{} denied by deny_warnings:\n{}",
+            match compiled.warnings.len() {
+                1 => "1 warning".to_string(),
+                n => format!("{} warnings", n),
+            },
+            compiled.warnings.join("\n")
+        )
+        .into());
+    }
+    for warning in &compiled.warnings {
+        report("warning", warning);
+    }
+    if options.preview {
+        eprint!("{}", compiled.preview());
+    }
+    // Keep the `include_bytes!`s, so the check reruns when the assets change.
+    if options.check_only {
+        return Ok(());
+    }
+
+    compiled.write_to(&mut out, options)?;
+    if let Some(path) = &options.shared_palette {
+        compiled.write_shared_palette(Path::new(path))?;
+    }
+    if let Some(path) = &options.c_output {
+        compiled.write_c_files(Path::new(path), options)?;
+    }
+    if let Some(path) = &options.export_sheet {
+        compiled.export_sheet(Path::new(path), options)?;
+    }
+    Ok(())
+}
+
+/// Shows a warning or note to the user. With the `nightly` feature, it's a diagnostic on the
+/// `compile!` invocation, like the compiler's own warnings. Otherwise it goes to stderr, which
+/// cargo shows on every build of a path or workspace crate, once for each target that expands
+/// the macro, but hides for crates from a registry unless their build fails.
+fn report(level: &str, message: &str) {
+    #[cfg(feature = "nightly")]
+    if proc_macro::is_available() {
+        let level = match level {
+            "warning" => proc_macro::Level::Warning,
+            _ => proc_macro::Level::Note,
+        };
+        proc_macro::Diagnostic::spanned(proc_macro::Span::call_site(), level, message).emit();
+        return;
+    }
+
+    eprintln!("{}: {}", level, message);
+}
+
+/// Extensions of the sprite descriptors picked up while walking the directory. JSON is read
+/// with the YAML parser, since YAML is a superset of it, and CSV and TSV files are tables of
+/// sprite rects.
+const DESC_EXTENSIONS: &[&str] = &["yml", "json", "csv", "tsv"];
+
+/// Settings for every descriptor in the directory it's in and the directories below it, which
+/// take precedence over the settings of defaults files further up.
+const DEFAULTS_FILE: &str = "_defaults.yml";
+
+/// The descriptor fields a defaults file can set, besides `sprite`, which holds fields for
+/// every sprite. Paths would be relative to each descriptor using them, so they're left out.
+const DEFAULTS_FIELDS: &[&str] = &["transparent_is", "group", "priority", "allow_overlaps"];
+
+/// Reads every descriptor in `dir`, writing `include_bytes!`s of the files read to `out` so
+/// the compiler knows to rebuild when they change. `dir` may also be a single descriptor or
+/// PNG to read just that.
+fn read_dir(dir: &str, options: &Options, mut out: impl Write) -> Result<SpritesBuilder> {
     let mut builder = SpritesBuilder::default();
 
-    for entry in walkdir::WalkDir::new(dir) {
+    if let Some(config) = &options.config_file {
+        use_path(&mut out, options, config)?;
+    }
+    if let Some(path) = &options.shared_palette {
+        if Path::new(path).exists() {
+            use_path(&mut out, options, path)?;
+        }
+    }
+
+    if Path::new(dir).is_file() {
+        add_file(
+            &mut builder,
+            Path::new(dir),
+            &Mapping::new(),
+            options,
+            &mut out,
+        )?;
+        return Ok(builder);
+    }
+
+    let mut defaults_files = HashMap::new();
+    for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
         let entry = entry?;
         if entry.file_type().is_dir() {
             continue;
         }
-        if entry.path().extension() != Some(OsStr::new("yml")) {
+        let ext = entry.path().extension().and_then(OsStr::to_str);
+        if !ext.is_some_and(|ext| DESC_EXTENSIONS.contains(&ext)) {
+            continue;
+        }
+        if entry.file_name() == options::CONFIG_FILE
+            || entry.file_name() == DEFAULTS_FILE
+            || is_excluded(dir, entry.path(), options)
+        {
             continue;
         }
 
-        let desc_path = use_path(&mut out, entry.path())?;
-        let png_path = use_path(&mut out, entry.path().with_extension("png"))?;
-
-        let desc: SpritesDesc = serde_yaml::from_reader(File::open(desc_path)?)?;
-        let bmp = decode32_file(png_path)?;
-        builder.add(desc, bmp);
+        let defaults = dir_defaults(
+            Path::new(dir),
+            entry.path().parent().unwrap_or(Path::new(dir)),
+            &mut defaults_files,
+            options,
+            &mut out,
+        )?;
+        add_file(&mut builder, entry.path(), &defaults, options, &mut out)?;
     }
 
-    let compiled = builder.compile()?;
-    compiled.write_to(&mut out)?;
-    Ok(out)
+    Ok(builder)
 }
 
-fn use_path<P: AsRef<Path>>(mut out: impl Write, p: P) -> std::result::Result<P, std::fmt::Error> {
-    // TODO(shelbyd): Import relative to actual root.
-    write!(
-        &mut out,
-        "const _: &[u8] = include_bytes!(\"../{}\");",
-        p.as_ref().display(),
-    )?;
+/// The settings of every defaults file from `root` down to `dir`, with deeper ones winning.
+/// Each file is read once into `read`.
+fn dir_defaults(
+    root: &Path,
+    dir: &Path,
+    read: &mut HashMap<PathBuf, Mapping>,
+    options: &Options,
+    mut out: impl Write,
+) -> Result<Mapping> {
+    let relative = dir.strip_prefix(root).unwrap_or(Path::new(""));
+    let mut dirs = relative.ancestors().collect::<Vec<_>>();
+    dirs.reverse();
 
-    Ok(p)
+    let mut defaults = Mapping::new();
+    for sub in dirs {
+        let path = root.join(sub).join(DEFAULTS_FILE);
+        if !path.exists() {
+            continue;
+        }
+        if !read.contains_key(&path) {
+            use_path(&mut out, options, &path)?;
+            read.insert(path.clone(), read_defaults(&path)?);
+        }
+        for (key, value) in &read[&path] {
+            // Sprite fields are combined with those from further up, rather than replacing them.
+            match (defaults.get_mut(key), value) {
+                (Some(Value::Mapping(sprite)), Value::Mapping(deeper))
+                    if key.as_str() == Some("sprite") =>
+                {
+                    sprite.extend(deeper.clone())
+                }
+                _ => {
+                    defaults.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+    Ok(defaults)
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(deny_unknown_fields)]
-struct SpritesDesc {
-    sprites: HashMap<String, Sprite>,
+fn read_defaults(path: &Path) -> Result<Mapping> {
+    let mut value: Value = serde_yaml::from_reader(File::open(path)?)?;
+    merge_keys(&mut value);
+    let defaults = match value {
+        Value::Null => Mapping::new(),
+        Value::Mapping(m) => m,
+        _ => return Err(format!("{} must be a mapping of fields", path.display()).into()),
+    };
+
+    for (key, value) in &defaults {
+        match (key.as_str(), value) {
+            (Some(key), _) if DEFAULTS_FIELDS.contains(&key) => {}
+            (Some("sprite"), Value::Mapping(_)) => {}
+            (Some("sprite"), _) => {
+                return Err(format!(
+                    "{} has a sprite field that isn't a mapping of sprite fields",
+                    path.display()
+                )
+                .into())
+            }
+            _ => {
+                return Err(format!(
+                    "{} has field {:?}, but defaults can only set {} and sprite",
+                    path.display(),
+                    key,
+                    DEFAULTS_FIELDS.join(", ")
+                )
+                .into())
+            }
+        }
+    }
+    Ok(defaults)
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(deny_unknown_fields)]
-struct Sprite {
-    rect: (usize, usize, usize, usize),
+/// Fills in the fields of the descriptor `desc` missing from it, and from each of its sprites,
+/// from `defaults`.
+fn apply_defaults(desc: &mut Value, defaults: &Mapping) {
+    let desc = match desc {
+        Value::Mapping(desc) => desc,
+        _ => return,
+    };
+    for (key, value) in defaults {
+        if key.as_str() != Some("sprite") {
+            if !desc.contains_key(key) {
+                desc.insert(key.clone(), value.clone());
+            }
+            continue;
+        }
+
+        let sprites = desc.get_mut(&Value::from("sprites"));
+        let sprites = sprites.and_then(Value::as_mapping_mut).into_iter();
+        for sprite in sprites.flat_map(|s| s.iter_mut().map(|(_, v)| v)) {
+            if let (Value::Mapping(sprite), Value::Mapping(fields)) = (sprite, value) {
+                for (field, v) in fields {
+                    if !sprite.contains_key(field) {
+                        sprite.insert(field.clone(), v.clone());
+                    }
+                }
+            }
+        }
+    }
 }
 
-#[derive(Default)]
-struct SpritesBuilder {
-    sprites: HashMap<String, Bitmap<RGBA>>,
+/// Adds the sprites in a descriptor, or in a PNG with a descriptor beside it, with the fields
+/// missing from the descriptor taken from `defaults`. A PNG without one is compiled as a single
+/// sprite of the whole image, named after the file.
+fn add_file(
+    builder: &mut SpritesBuilder,
+    path: &Path,
+    defaults: &Mapping,
+    options: &Options,
+    mut out: impl Write,
+) -> Result<()> {
+    let ext = path.extension().and_then(OsStr::to_str);
+    let desc_path = match ext {
+        Some("png") => DESC_EXTENSIONS
+            .iter()
+            .map(|ext| path.with_extension(ext))
+            .find(|p| p.exists()),
+        Some(ext) if DESC_EXTENSIONS.contains(&ext) => Some(path.to_owned()),
+        _ => return Err(format!("{} isn't a sprite descriptor or PNG", path.display()).into()),
+    };
+    trace!("reading {}", path.display());
+
+    let mut png_path = path.with_extension("png");
+    let mut desc_dir = None;
+    let desc: Option<SpritesDesc> = match desc_path {
+        Some(desc_path) => {
+            let desc_path = use_path(&mut out, options, desc_path)?;
+            let desc = read_desc(&desc_path, defaults)?;
+            builder.check_unknown_fields(&desc_path, &desc, options)?;
+            check_names(&desc_path, &desc)?;
+            for palette in &desc.palettes {
+                let path = desc_path.parent().unwrap_or(Path::new("")).join(palette);
+                let path = use_path(&mut out, options, path)?;
+                builder.include_palette(&path, read_palette(&path)?)?;
+            }
+            if let Some(image) = &desc.image {
+                png_path = desc_path.parent().unwrap_or(Path::new("")).join(image);
+            }
+            desc_dir = desc_path.parent().map(Path::to_owned);
+            Some(desc)
+        }
+        None => None,
+    };
+    let png_path = use_path(&mut out, options, png_path)?;
+    check_image_size(&png_path, options)?;
+    let mut bmp = decode32_file(&png_path)?;
+    check_decoded(&png_path, &bmp)?;
+    let transparency = desc
+        .as_ref()
+        .and_then(|d| d.transparent_is)
+        .unwrap_or(options.global_transparent);
+    apply_transparency(&png_path, &mut bmp, transparency)?;
+    if let Some(desc) = desc.as_ref().filter(|d| d.keep_indices) {
+        // 4bpp sprites are packed into palette banks, so can't keep the PNG's indices. Check
+        // before keeping them, which includes the PNG's palette.
+        if let Some(id) = four_bpp_sprite(desc, options) {
+            return Err(format!(
+                "{} has keep_indices, but sprite {} is 4bpp, which isn't supported",
+                png_path.display(),
+                id
+            )
+            .into());
+        }
+        builder.keep_indices(&png_path, &mut bmp)?;
+    }
+    if options.detect_scale {
+        if let Some(scale) = detect_scale(&bmp) {
+            bmp = downscale(&bmp, scale);
+            let dpi = match read_dpi(&png_path) {
+                Some(dpi) => format!(" and it's {:.0} DPI", dpi),
+                None => String::new(),
+            };
+            builder.warnings.push(format!(
+                "{} looks like a {}x scaled export, as every {}x{} block of pixels is one color{}, so it was scaled down to {}x{}",
+                png_path.display(),
+                scale,
+                scale,
+                scale,
+                dpi,
+                bmp.width,
+                bmp.height
+            ));
+        }
+    }
+
+    let desc = match desc {
+        Some(mut desc) => {
+            resolve_regions(&png_path, &mut desc, &mut bmp)?;
+            let dir = desc_dir.unwrap_or_default();
+            read_part_images(&dir, &mut desc, transparency, options, &mut out)?;
+            desc
+        }
+        None => {
+            let id = path
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .ok_or_else(|| format!("Invalid sprite file name {}", path.display()))?;
+            let sprite = Sprite {
+                rect: Some((0, 0, bmp.width, bmp.height)),
+                ..Sprite::default()
+            };
+            SpritesDesc {
+                sprites: [(id.to_owned(), sprite)].into_iter().collect(),
+                ..SpritesDesc::default()
+            }
+        }
+    };
+    let unused = options.report_unused.then(|| bmp.clone());
+    let covered = builder.add(desc, bmp, options)?;
+    if let Some(bmp) = unused {
+        builder.warn_unused_regions(&png_path, &bmp, &covered);
+    }
+    Ok(())
 }
 
-impl SpritesBuilder {
-    fn add(&mut self, sprites: SpritesDesc, bmp: Bitmap<RGBA>) {
-        for (id, desc) in sprites.sprites {
-            self.sprites.insert(id, extract_rect(&bmp, desc.rect));
+/// Reads the images in `dir` that `desc`'s sprites cut parts from besides the sheet, with the
+/// sheet's transparency.
+fn read_part_images(
+    dir: &Path,
+    desc: &mut SpritesDesc,
+    transparency: Transparency,
+    options: &Options,
+    mut out: impl Write,
+) -> Result<()> {
+    let images = desc
+        .sprites
+        .values()
+        .flat_map(|sprite| &sprite.parts)
+        .filter_map(|part| part.image.clone())
+        .collect::<BTreeSet<_>>();
+    for image in images {
+        let path = use_path(&mut out, options, dir.join(&image))?;
+        check_image_size(&path, options)?;
+        let mut bmp = decode32_file(&path)?;
+        check_decoded(&path, &bmp)?;
+        apply_transparency(&path, &mut bmp, transparency)?;
+        desc.images.0.insert(image, bmp);
+    }
+    Ok(())
+}
+
+/// Adds a sprite to `desc` for each region of `sheet` outlined in the `regions` key color, with
+/// the inside of the outline as its rect, and makes the outlines transparent.
+fn resolve_regions(path: &Path, desc: &mut SpritesDesc, sheet: &mut Bitmap<RGBA>) -> Result<()> {
+    let regions = match desc.regions.take() {
+        Some(regions) => regions,
+        None => return Ok(()),
+    };
+    let is_key = |p: &RGBA| p.a == 255 && [p.r, p.g, p.b] == regions.key;
+
+    // Flood fill each outline to find its bounds.
+    let mut seen = vec![false; sheet.buffer.len()];
+    let mut bounds = Vec::new();
+    for start in 0..sheet.buffer.len() {
+        if seen[start] || !is_key(&sheet.buffer[start]) {
+            continue;
+        }
+        seen[start] = true;
+        let mut stack = vec![start];
+        let (mut x0, mut y0, mut x1, mut y1) = (usize::MAX, usize::MAX, 0, 0);
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % sheet.width, i / sheet.width);
+            (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+
+            let neighbors = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < sheet.width).then_some(i + 1),
+                (y > 0).then(|| i - sheet.width),
+                (y + 1 < sheet.height).then_some(i + sheet.width),
+            ];
+            for n in neighbors.into_iter().flatten() {
+                if !seen[n] && is_key(&sheet.buffer[n]) {
+                    seen[n] = true;
+                    stack.push(n);
+                }
+            }
         }
+        bounds.push((x0, y0, x1 + 1 - x0, y1 + 1 - y0));
     }
+    bounds.sort_by_key(|&(x, y, _, _)| (y, x));
 
-    fn compile(self) -> Result<CompiledSprites> {
-        let mut compiled = CompiledSprites {
-            next_palette_index: Some(1),
-            palette: HashMap::new(),
-            sprites: HashMap::new(),
+    if !regions.names.is_empty() && regions.names.len() != bounds.len() {
+        return Err(format!(
+            "{} has {} regions outlined in {:?}, but {} region names",
+            path.display(),
+            bounds.len(),
+            regions.key,
+            regions.names.len()
+        )
+        .into());
+    }
+    for (i, (x, y, w, h)) in bounds.into_iter().enumerate() {
+        if w < 3 || h < 3 {
+            return Err(format!(
+                "{} has a {}x{} region of {:?} at ({}, {}), which is too small to outline a sprite",
+                path.display(),
+                w,
+                h,
+                regions.key,
+                x,
+                y
+            )
+            .into());
+        }
+        let name = match regions.names.get(i) {
+            Some(name) => name.clone(),
+            None => format!("region_{}", i),
         };
+        let sprite = desc.sprites.entry(name).or_default();
+        if sprite.rect.is_none() && sprite.rect_pct.is_none() && sprite.parts.is_empty() {
+            sprite.rect = Some((x + 1, y + 1, w - 2, h - 2));
+        }
+    }
+
+    for pixel in sheet.buffer.iter_mut().filter(|p| is_key(p)) {
+        pixel.a = 0;
+    }
+    Ok(())
+}
 
-        for (id, bmp) in self.sprites {
-            let x_tiles = bmp.width / 8;
-            let y_tiles = bmp.height / 8;
+/// The id of a sprite of `desc` that's 4bpp, counting the ones its regions will add, which are
+/// named `region_{i}` unless `names` are given.
+fn four_bpp_sprite(desc: &SpritesDesc, options: &Options) -> Option<String> {
+    let is_four =
+        |sprite: Option<&Sprite>| sprite.and_then(|s| s.bpp).unwrap_or(options.bpp) == Bpp::Four;
+    let mut region_ids = match &desc.regions {
+        Some(regions) if regions.names.is_empty() => vec!["region_0".to_owned()],
+        Some(regions) => regions.names.clone(),
+        None => Vec::new(),
+    };
+    region_ids.retain(|id| !desc.sprites.contains_key(id) && is_four(None));
+    let sprites = desc.sprites.iter().filter(|(_, s)| is_four(Some(s)));
+    sprites.map(|(id, _)| id.clone()).chain(region_ids).next()
+}
+
+/// Reads a sprite descriptor, expanding YAML merge keys so sprites can share fields.
+fn read_desc(path: &Path, defaults: &Mapping) -> Result<SpritesDesc> {
+    let table = |delimiter| -> Result<Value> {
+        region_table::parse(&std::fs::read_to_string(path)?, delimiter)
+            .map_err(|e| format!("Reading {}: {}", path.display(), e).into())
+    };
+    let mut value: Value = match path.extension().and_then(OsStr::to_str) {
+        Some("csv") => table(',')?,
+        Some("tsv") => table('\t')?,
+        _ => serde_yaml::from_reader(File::open(path)?)?,
+    };
+    merge_keys(&mut value);
+    apply_defaults(&mut value, defaults);
+    Ok(serde_yaml::from_value(value)?)
+}
 
-            let mut tiles = Vec::new();
-            for y_tile in 0..y_tiles {
-                for x_tile in 0..x_tiles {
-                    let tile = extract_rect(&bmp, (x_tile * 8, y_tile * 8, 8, 8))
-                        .buffer
-                        .into_iter()
-                        .map(|c| compiled.palette_index(c))
-                        .collect::<Result<_>>()?;
-                    tiles.push(tile);
+/// Replaces each `<<` key in `value` with the fields of the mapping, or list of mappings, it
+/// refers to. Fields already in the mapping win over merged ones, and earlier mappings in a
+/// list win over later ones.
+fn merge_keys(value: &mut Value) {
+    match value {
+        Value::Mapping(map) => {
+            for (_, v) in map.iter_mut() {
+                merge_keys(v);
+            }
+            let merged = match map.remove(&Value::String("<<".to_owned())) {
+                Some(Value::Sequence(seq)) => seq,
+                Some(other) => vec![other],
+                None => return,
+            };
+            for source in merged {
+                if let Value::Mapping(source) = source {
+                    for (k, v) in source {
+                        if !map.contains_key(&k) {
+                            map.insert(k, v);
+                        }
+                    }
                 }
             }
-            compiled.sprites.insert(id, tiles);
         }
+        Value::Sequence(seq) => seq.iter_mut().for_each(merge_keys),
+        _ => {}
+    }
+}
 
-        Ok(compiled)
+/// Reads the colors already in a `shared_palette` file, if it exists, as pins.
+fn read_shared_palette(path: &Path) -> Result<BTreeMap<[u8; 3], u8>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
     }
+
+    let palette = read_palette(path)?;
+    Ok(palette.into_iter().map(|(i, c)| (c, i)).collect())
 }
 
-fn extract_rect<P: Copy>(
-    input: &Bitmap<P>,
-    (base_x, base_y, w, h): (usize, usize, usize, usize),
-) -> Bitmap<P> {
-    let mut result = Bitmap {
-        buffer: Vec::new(),
-        width: w,
-        height: h,
+/// Reads a palette file of colors by index, as written for `shared_palette`, or a raw
+/// BGR555 `.pal` or ACT `.act` file from other tools.
+fn read_palette(path: &Path) -> Result<BTreeMap<u8, [u8; 3]>> {
+    let read = || -> Result<BTreeMap<u8, [u8; 3]>> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("pal") => palette_files::parse_raw(&std::fs::read(path)?),
+            Some("act") => palette_files::parse_act(&std::fs::read(path)?),
+            _ => Ok(serde_yaml::from_reader(File::open(path)?)?),
+        }
     };
-    let buf = &mut result.buffer;
+    read().map_err(|e| format!("Reading {}: {}", path.display(), e).into())
+}
 
-    for y in base_y..(base_y + h) {
-        for x in base_x..(base_x + w) {
-            let index = y * input.width + x;
-            buf.push(input.buffer[index]);
-        }
+/// The biggest factor `bmp` could have been scaled up by, with every block of that many pixels
+/// each way the same color, and still be whole tiles at its original size.
+fn detect_scale(bmp: &Bitmap<RGBA>) -> Option<usize> {
+    // Transparent pixels look the same whatever their color channels.
+    let pixel = |x: usize, y: usize| match bmp.buffer[y * bmp.width + x] {
+        p if p.a == 0 => RGBA::new(0, 0, 0, 0),
+        p => p,
+    };
+
+    (2..=8).rev().find(|&n| {
+        bmp.width.is_multiple_of(n * 8)
+            && bmp.height.is_multiple_of(n * 8)
+            && bmp.width > 0
+            && bmp.height > 0
+            && (0..bmp.height)
+                .all(|y| (0..bmp.width).all(|x| pixel(x, y) == pixel(x / n * n, y / n * n)))
+    })
+}
+
+/// Keeps the top left pixel of every `scale`x`scale` block.
+fn downscale(bmp: &Bitmap<RGBA>, scale: usize) -> Bitmap<RGBA> {
+    let (width, height) = (bmp.width / scale, bmp.height / scale);
+    let buffer = (0..height)
+        .flat_map(|y| (0..width).map(move |x| bmp.buffer[y * scale * bmp.width + x * scale]))
+        .collect();
+    Bitmap {
+        buffer,
+        width,
+        height,
     }
+}
 
-    result
+/// The horizontal resolution from a PNG's pHYs chunk, if it gives one in real units.
+fn read_dpi(path: &Path) -> Option<f64> {
+    let mut decoder = lodepng::Decoder::new();
+    decoder.decode_file(path).ok()?;
+    let info = decoder.info_png();
+    // Unit 1 is pixels per meter.
+    (info.phys_defined && info.phys_unit == 1).then_some(info.phys_x as f64 * 0.0254)
 }
 
-#[derive(Debug)]
-struct CompiledSprites {
-    next_palette_index: Option<u8>,
-    palette: HashMap<RGB<u8>, u8>,
-    sprites: HashMap<String, Vec<Vec<u8>>>,
+/// Errors on images wider or taller than `max_image_size` from their header, before decoding
+/// them allocates a buffer for every pixel. Other problems are left for the decode to report.
+fn check_image_size(path: &Path, options: &Options) -> Result<()> {
+    let header = std::fs::read(path)?;
+    if let Ok((width, height)) = lodepng::Decoder::new().inspect(&header) {
+        if width.max(height) > options.max_image_size {
+            return Err(format!(
+                "{} is {}x{}, bigger than max_image_size {}, so probably isn't a sprite sheet",
+                path.display(),
+                width,
+                height,
+                options.max_image_size
+            )
+            .into());
+        }
+    }
+    Ok(())
 }
 
-impl CompiledSprites {
-    fn palette_index(&mut self, color: RGBA) -> Result<u8> {
-        use std::collections::hash_map::Entry;
+/// Makes the pixels `transparency` picks out fully transparent.
+fn apply_transparency(
+    path: &Path,
+    bmp: &mut Bitmap<RGBA>,
+    transparency: Transparency,
+) -> Result<()> {
+    let color = match transparency {
+        Transparency::Alpha => return Ok(()),
+        Transparency::Color(color) => color,
+        Transparency::Index(i) => {
+            let mut decoder = lodepng::Decoder::new();
+            decoder.decode_file(path)?;
+            let palette = decoder.info_png().color.palette();
+            let c = palette.get(i as usize).ok_or_else(|| match palette.len() {
+                0 => format!(
+                    "{} has transparent_is index {}, but isn't an indexed PNG",
+                    path.display(),
+                    i
+                ),
+                n => format!(
+                    "{} has transparent_is index {}, but its palette only has {} colors",
+                    path.display(),
+                    i,
+                    n
+                ),
+            })?;
+            [c.r, c.g, c.b]
+        }
+    };
 
-        if color.a == 0 {
-            return Ok(0);
+    for pixel in &mut bmp.buffer {
+        if [pixel.r, pixel.g, pixel.b] == color {
+            pixel.a = 0;
         }
-        assert_eq!(color.a, 255);
+    }
+    Ok(())
+}
 
-        match (self.palette.entry(color.rgb()), self.next_palette_index) {
-            (Entry::Occupied(o), _) => Ok(*o.get()),
-            (Entry::Vacant(e), Some(i)) => {
-                self.next_palette_index = i.checked_add(1);
-                e.insert(i);
-                Ok(i)
+/// Guards against a truncated decode, which would otherwise silently produce garbage tiles.
+fn check_decoded(path: &Path, bmp: &Bitmap<RGBA>) -> Result<()> {
+    if bmp.buffer.len() != bmp.width * bmp.height {
+        return Err(format!(
+            "{} decoded to {} pixels, but is {}x{}",
+            path.display(),
+            bmp.buffer.len(),
+            bmp.width,
+            bmp.height
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn is_excluded(dir: &str, path: &Path, options: &Options) -> bool {
+    let relative = path.strip_prefix(dir).unwrap_or(path);
+    let relative = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    options
+        .exclude
+        .iter()
+        .any(|pattern| glob::matches(pattern, &relative))
+}
+
+fn use_path<P: AsRef<Path>>(
+    mut out: impl Write,
+    options: &Options,
+    p: P,
+) -> std::result::Result<P, std::fmt::Error> {
+    // Absolute paths, like the config file's under `CARGO_MANIFEST_DIR`, need no base.
+    let base = match p.as_ref().is_absolute() {
+        true => "",
+        false => options.include_base.as_deref().unwrap_or("../"),
+    };
+    writeln!(
+        &mut out,
+        "const _: &[u8] = include_bytes!(\"{}{}\");",
+        base,
+        p.as_ref().display(),
+    )?;
+
+    Ok(p)
+}
+
+/// The prefix that makes paths relative to the working directory relative to the directory of
+/// `file` instead, which is what `include_bytes!` resolves paths from.
+fn include_base(file: &Path, cwd: &Path) -> String {
+    let dir = file.parent().unwrap_or_else(|| Path::new(""));
+    let relative = dir
+        .components()
+        .all(|c| matches!(c, std::path::Component::Normal(_)));
+
+    if relative {
+        "../".repeat(dir.components().count())
+    } else {
+        format!("{}/", cwd.display())
+    }
+}
+
+/// Fields a descriptor has that aren't used, by name. Collected rather than denied so they can
+/// be allowed with `allow_unknown_fields`.
+type UnknownFields = BTreeMap<String, serde_yaml::Value>;
+
+#[derive(Deserialize, Debug, Default)]
+struct SpritesDesc {
+    /// The sheet to cut the sprites from, relative to the descriptor. Defaults to the PNG of the
+    /// same name beside it.
+    image: Option<PathBuf>,
+    /// Named origins within the sheet that sprite rects can be relative to.
+    #[serde(default)]
+    anchors: HashMap<String, (usize, usize)>,
+    /// Don't warn about sprites whose rects overlap, for sheets that share pixels on purpose.
+    #[serde(default)]
+    allow_overlaps: bool,
+    /// Which of the sheet's pixels are transparent, besides fully transparent ones, instead of
+    /// the `global_transparent` option.
+    transparent_is: Option<Transparency>,
+    /// Where the sheet's rects and anchors are measured from, for descriptors exported by tools
+    /// that put the origin at the bottom left.
+    #[serde(default)]
+    coordinate_origin: CoordinateOrigin,
+    /// Give each pixel the index it has in the sheet's own palette, which must be an indexed
+    /// PNG, for art drawn against the game's palette. Pixels of index 0 are transparent.
+    #[serde(default)]
+    keep_indices: bool,
+    /// Emit this file's sprites in a module of this name, shared with any other descriptors
+    /// naming the same group.
+    group: Option<String>,
+    /// Palette files in the format of `shared_palette`, or raw BGR555 `.pal` or ACT `.act`
+    /// files, whose colors keep their indices, with earlier files taking priority for colors in
+    /// several. Give them an extension other than a descriptor's, like `.palette`, if they're in
+    /// the compiled directory.
+    #[serde(default)]
+    palettes: Vec<PathBuf>,
+    /// Sprites from files with a higher priority get their colors assigned palette indices
+    /// first, to keep the most important sprites' indices stable as others change.
+    #[serde(default)]
+    priority: i32,
+    /// Sprites outlined on the sheet in a key color, in addition to those in `sprites`.
+    regions: Option<Regions>,
+    #[serde(default)]
+    sprites: BTreeMap<String, Sprite>,
+    /// The images parts are cut from besides the sheet, by the path they give.
+    #[serde(skip)]
+    images: PartImages,
+    #[serde(flatten)]
+    unknown: UnknownFields,
+}
+
+/// Decoded images by path, listing just the paths for `Debug`, as bitmaps don't implement it.
+#[derive(Default)]
+struct PartImages(HashMap<PathBuf, Bitmap<RGBA>>);
+
+impl std::fmt::Debug for PartImages {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+/// Sprites marked by drawing a one pixel outline around each in a key color, for artists who'd
+/// rather mark them on the sheet than measure rects.
+#[derive(Deserialize, Debug)]
+struct Regions {
+    /// The color of the outlines, which are made transparent.
+    key: [u8; 3],
+    /// The name of each outlined sprite, in order of their top left corners row by row. They're
+    /// named `region_0`, `region_1` and so on by default. A sprite in `sprites` by the same
+    /// name without a rect takes its rect from the outline.
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(flatten)]
+    unknown: UnknownFields,
+}
+
+impl SpritesDesc {
+    /// Every unknown field, described by where it is.
+    fn unknown_fields(&self) -> Vec<String> {
+        let mut fields = self
+            .unknown
+            .keys()
+            .map(|k| format!("`{}`", k))
+            .collect::<Vec<_>>();
+        if let Some(regions) = &self.regions {
+            fields.extend(
+                regions
+                    .unknown
+                    .keys()
+                    .map(|k| format!("`{}` in regions", k)),
+            );
+        }
+        for (id, sprite) in &self.sprites {
+            fields.extend(
+                sprite
+                    .unknown
+                    .keys()
+                    .map(|k| format!("`{}` in sprite {}", k, id)),
+            );
+            for part in &sprite.parts {
+                fields.extend(
+                    part.unknown
+                        .keys()
+                        .map(|k| format!("`{}` in a part of sprite {}", k, id)),
+                );
             }
-            (Entry::Vacant(_), None) => Err("Too many colors to fit into single palette".into()),
         }
+        fields
     }
+}
 
-    fn write_to(&self, mut out: impl std::fmt::Write) -> Result<()> {
-        writeln!(out, "use ::gba::mmio_types::Color;\n")?;
+#[derive(Deserialize, Debug, Default)]
+struct Sprite {
+    /// Makes the rect, or the rects of each part, relative to this anchor.
+    anchor: Option<String>,
+    rect: Option<Rect>,
+    /// The rect as fractions of the image's width and height, for descriptors shared between
+    /// differently scaled exports. Must come out as whole tiles.
+    rect_pct: Option<(f64, f64, f64, f64)>,
+    /// Composites the sprite from several rects instead of a single one. Later parts are drawn
+    /// over earlier ones.
+    #[serde(default)]
+    parts: Vec<Part>,
+    /// Source colors to replace with target colors before indexing.
+    #[serde(default)]
+    recolor: HashMap<[u8; 3], [u8; 3]>,
+    /// Splits the rect horizontally into this many equally sized animation frames.
+    frames: Option<usize>,
+    /// How long to show each frame for, in ticks.
+    #[serde(default)]
+    durations: Vec<u8>,
+    /// Emit the tile data compressed for the BIOS decompression routines.
+    compress: Option<Compression>,
+    /// Overrides the `bpp` option for this sprite.
+    bpp: Option<Bpp>,
+    #[serde(default)]
+    kind: Kind,
+    /// With `kind: font`, the character each glyph is, in the order the tiles are emitted.
+    charset: Option<String>,
+    /// With `kind: nineslice`, the widths of the left and right edges and the heights of the
+    /// top and bottom edges, as `[left, top, right, bottom]` in pixels. Must be whole tiles.
+    insets: Option<[usize; 4]>,
+    /// Which of the sprite's tiles, in the usual order, to emit at each position instead, for
+    /// layouts the usual order doesn't suit. Must list every tile once.
+    tile_order: Option<Vec<usize>>,
+    /// The frames animate a region of background tiles, by stepping the tile base it's drawn
+    /// from, rather than an OBJ.
+    #[serde(default)]
+    tile_anim: bool,
+    /// Emit each distinct tile of the frames once, with the tiles of each frame as indices into
+    /// them, for animations where most of the sprite stays still.
+    #[serde(default)]
+    share_tiles: bool,
+    /// The OBJ priority to draw the sprite with, from 0 (in front) to 3.
+    obj_priority: Option<u8>,
+    /// Where the sprite stacks among the others, like the order of the layers it was drawn on,
+    /// for compositing code. Once any sprite has one, every sprite gets a `_Z`, of 0 by default.
+    z: Option<u8>,
+    /// Emit the sprite in a `_SPRITES` list for each of these tags, with every other sprite
+    /// tagged the same, for loading sprites by category across files and groups.
+    #[serde(default)]
+    tags: BTreeSet<String>,
+    /// With bpp 4, the palette bank to put the sprite's colors in, rather than letting bank
+    /// packing choose, for banks the game sets up itself. Other sprites can still share it.
+    palbank: Option<u8>,
+    /// Crop fully transparent tiles from the edges of the sprite, shrinking every frame alike.
+    #[serde(default)]
+    trim: bool,
+    /// The number of tiles the sprite should come out as, across every frame, as a check that
+    /// its rect is the size intended.
+    expect_tiles: Option<usize>,
+    #[serde(flatten)]
+    unknown: UnknownFields,
+}
 
-        let mut unwritten_colors = self
-            .palette
+/// The corner of a sheet its coordinates count from, with y going away from it.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+enum CoordinateOrigin {
+    #[default]
+    TopLeft,
+    /// Rects are given by their bottom left corner, counting up from the bottom of the sheet.
+    BottomLeft,
+}
+
+/// What a sprite is drawn with on the GBA.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    /// Tiles for OBJs, indexing into the palette.
+    #[default]
+    Obj,
+    /// Tiles for a background, where index 0 is the visible backdrop rather than see-through,
+    /// so pixels of the `backdrop` color use it instead of taking an index of their own.
+    Bg,
+    /// A bitmap of BGR555 colors for the Mode 3 framebuffer, bypassing the palette.
+    Mode3,
+    /// A grid of 8x8 glyphs, one tile each, for the characters of its `charset`.
+    Font,
+    /// A UI element cut by its `insets` into nine OBJ sprites, the corners, edges and center,
+    /// for stretching to any size.
+    NineSlice,
+}
+
+/// The name of each slice of a `kind: nineslice` sprite, row by row.
+const NINE_SLICES: [[&str; 3]; 3] = [
+    ["top_left", "top", "top_right"],
+    ["left", "center", "right"],
+    ["bottom_left", "bottom", "bottom_right"],
+];
+
+/// The size of the Mode 3 framebuffer.
+const MODE3_SIZE: (usize, usize) = (240, 160);
+
+#[derive(Deserialize, Debug)]
+struct Part {
+    rect: Rect,
+    /// Where to place this part's top left corner in the sprite.
+    #[serde(default)]
+    at: (usize, usize),
+    /// Cut the part from this PNG instead of the sheet, relative to the descriptor, ignoring the
+    /// sprite's anchor. A sprite with parts from other images must be covered by parts on whole
+    /// tiles.
+    image: Option<PathBuf>,
+    #[serde(flatten)]
+    unknown: UnknownFields,
+}
+
+#[derive(Default, Clone)]
+struct SpritesBuilder {
+    sprites: BTreeMap<String, ExtractedSprite>,
+    /// `kind: mode3` sprites, with their group.
+    bitmaps: BTreeMap<String, (Option<String>, Bitmap<RGBA>)>,
+    /// The insets of `kind: nineslice` sprites, whose slices are in `sprites`, with their group.
+    nineslices: BTreeMap<String, (Option<String>, [usize; 4])>,
+    /// The colors of palettes included by descriptors, by index, with the file each is from.
+    included_palette: BTreeMap<u8, ([u8; 3], PathBuf)>,
+    warnings: Vec<String>,
+}
+
+#[derive(Clone)]
+struct ExtractedSprite {
+    /// Every frame of the sprite, side by side.
+    bmp: Bitmap<RGBA>,
+    frames: usize,
+    durations: Vec<u8>,
+    compression: Option<Compression>,
+    group: Option<String>,
+    bpp: Option<Bpp>,
+    priority: i32,
+    obj_priority: Option<u8>,
+    z: Option<u8>,
+    tags: BTreeSet<String>,
+    palbank: Option<u8>,
+    tile_order: Option<Vec<usize>>,
+    tile_anim: bool,
+    share_tiles: bool,
+    kind: Kind,
+    charset: Option<String>,
+    /// Where the sprite's top left corner was in its rect before `trim`, if trimmed.
+    offset: Option<(usize, usize)>,
+    /// Where each frame's top left pixel is on the sheet, if cut from a single rect.
+    sheet_frames: Option<Vec<(usize, usize)>>,
+}
+
+impl ExtractedSprite {
+    /// Every visible color of the sprite.
+    fn colors(&self) -> BTreeSet<RGB<u8>> {
+        self.bmp
+            .buffer
             .iter()
-            .map(|(&c, &i)| (i, c))
-            .collect::<HashMap<_, _>>();
+            .filter(|c| c.a != 0)
+            .map(|c| c.rgb())
+            .collect()
+    }
+}
 
-        writeln!(out, "pub const PALETTE: &'static [Color] = &[")?;
-        for i in 0..=255 {
-            if unwritten_colors.is_empty() {
-                break;
+impl SpritesBuilder {
+    /// Errors on the first unknown field in `desc`, or warns about all of them if
+    /// `allow_unknown_fields` is set.
+    fn check_unknown_fields(
+        &mut self,
+        path: &Path,
+        desc: &SpritesDesc,
+        options: &Options,
+    ) -> Result<()> {
+        for field in desc.unknown_fields() {
+            if !options.allow_unknown_fields {
+                return Err(format!("{} has unknown field {}", path.display(), field).into());
             }
-            let c = unwritten_colors.remove(&i).unwrap_or(RGB::default());
-            writeln!(
-                out,
-                "    Color::from_rgb({}, {}, {}),",
-                c.r >> 3,
-                c.g >> 3,
-                c.b >> 3,
-            )?;
+            self.warnings.push(format!(
+                "{} has unknown field {}, which is ignored",
+                path.display(),
+                field
+            ));
         }
-        writeln!(out, "];\n")?;
+        Ok(())
+    }
 
-        for (id, tiles) in &self.sprites {
-            writeln!(
-                out,
-                "pub const {}: &'static [[u8; 64]] = &[",
-                id.to_uppercase()
+    /// Adds the sprites cut from `bmp`, returning every rect of it they use.
+    fn add(
+        &mut self,
+        sprites: SpritesDesc,
+        bmp: Bitmap<RGBA>,
+        options: &Options,
+    ) -> Result<Vec<Rect>> {
+        let mut rects = Vec::new();
+        let mut covered = Vec::new();
+        for (id, desc) in sprites.sprites {
+            if self.is_defined(&id) {
+                return Err(format!("Sprite {} is defined more than once", id).into());
+            }
+
+            let (mut sprite_bmp, sources) = extract_sprite(
+                &sprites.anchors,
+                &sprites.images.0,
+                sprites.coordinate_origin,
+                &id,
+                &desc,
+                &bmp,
             )?;
+            trace!(
+                "extracted sprite {} ({}x{})",
+                id,
+                sprite_bmp.width,
+                sprite_bmp.height
+            );
+            let rect = desc.parts.is_empty().then(|| sources[0]);
+            // Composites reuse shared parts of the sheet by design, so only check plain rects.
+            if desc.parts.is_empty() {
+                rects.extend(sources.iter().map(|&rect| (id.clone(), rect)));
+            }
+            covered.extend(sources);
 
-            for tile in tiles {
-                writeln!(out, "    {:?},", tile)?;
+            if desc.kind == Kind::Mode3 {
+                if sprite_bmp.width > MODE3_SIZE.0 || sprite_bmp.height > MODE3_SIZE.1 {
+                    return Err(format!(
+                        "Sprite {} is {}x{}, which is bigger than the {}x{} Mode 3 screen",
+                        id, sprite_bmp.width, sprite_bmp.height, MODE3_SIZE.0, MODE3_SIZE.1
+                    )
+                    .into());
+                }
+                self.recolor(&id, &mut sprite_bmp, &desc.recolor);
+                self.bitmaps.insert(id, (sprites.group.clone(), sprite_bmp));
+                continue;
             }
 
-            writeln!(out, "];")?;
-        }
+            match (desc.kind, desc.insets) {
+                (Kind::NineSlice, None) => {
+                    return Err(format!("Sprite {} has kind: nineslice, but no insets", id).into())
+                }
+                (Kind::NineSlice, Some(insets)) => {
+                    if desc.frames.unwrap_or(1) != 1 {
+                        return Err(format!(
+                            "Sprite {} has kind: nineslice, so can't have frames",
+                            id
+                        )
+                        .into());
+                    }
+                    self.recolor(&id, &mut sprite_bmp, &desc.recolor);
+                    for (slice, slice_bmp) in nine_slices(&id, &sprite_bmp, insets)? {
+                        let slice_id = format!("{}_{}", id, slice);
+                        if self.is_defined(&slice_id) {
+                            return Err(format!(
+                                "Sprite {} is defined more than once, as a slice of {} too",
+                                slice_id, id
+                            )
+                            .into());
+                        }
+                        self.sprites.insert(
+                            slice_id,
+                            ExtractedSprite {
+                                bmp: slice_bmp,
+                                frames: 1,
+                                durations: Vec::new(),
+                                compression: desc.compress,
+                                group: sprites.group.clone(),
+                                bpp: desc.bpp,
+                                priority: sprites.priority,
+                                obj_priority: desc.obj_priority,
+                                z: desc.z,
+                                tags: desc.tags.clone(),
+                                palbank: desc.palbank,
+                                tile_order: None,
+                                tile_anim: false,
+                                share_tiles: false,
+                                kind: Kind::Obj,
+                                charset: None,
+                                offset: None,
+                                sheet_frames: None,
+                            },
+                        );
+                    }
+                    self.nineslices.insert(id, (sprites.group.clone(), insets));
+                    continue;
+                }
+                (_, Some(_)) => {
+                    return Err(
+                        format!("Sprite {} has insets, but isn't kind: nineslice", id).into(),
+                    )
+                }
+                (_, None) => {}
+            }
 
-        Ok(())
-    }
+            let frames = desc.frames.unwrap_or(1);
+            if frames == 0 {
+                return Err(format!("Sprite {} must have at least one frame", id).into());
+            }
+            if sprite_bmp.width % frames != 0 {
+                return Err(format!(
+                    "Sprite {} is {} pixels wide, which doesn't split into {} frames",
+                    id, sprite_bmp.width, frames
+                )
+                .into());
+            }
+            if !desc.durations.is_empty() && desc.durations.len() != frames {
+                return Err(format!(
+                    "Sprite {} has {} durations for {} frames",
+                    id,
+                    desc.durations.len(),
+                    frames
+                )
+                .into());
+            }
+
+            let offset = match desc.trim {
+                true => {
+                    let (trimmed, offset) = trim(&sprite_bmp, frames, options.alpha_threshold);
+                    sprite_bmp = trimmed;
+                    Some(offset)
+                }
+                false => None,
+            };
+            let sheet_frames = rect.map(|(x, y, w, _)| {
+                let (offset_x, offset_y) = offset.unwrap_or((0, 0));
+                (0..frames)
+                    .map(|frame| (x + frame * (w / frames) + offset_x, y + offset_y))
+                    .collect()
+            });
+
+            let frame_width = sprite_bmp.width / frames;
+            if frame_width % 8 != 0 || sprite_bmp.height % 8 != 0 {
+                self.warnings.push(format!(
+                    "Sprite {} is {}x{}, which isn't whole 8x8 tiles, so the pixels past its last whole tiles are dropped",
+                    id, frame_width, sprite_bmp.height
+                ));
+            }
+
+            let tiles = (frame_width / 8) * frames * (sprite_bmp.height / 8);
+            match desc.expect_tiles {
+                Some(expected) if expected != tiles => {
+                    return Err(format!(
+                        "Sprite {} is {} tiles, but expect_tiles is {}",
+                        id, tiles, expected
+                    )
+                    .into())
+                }
+                _ => {}
+            }
+            if let Some(order) = &desc.tile_order {
+                let mut sorted = order.clone();
+                sorted.sort_unstable();
+                if !sorted.iter().copied().eq(0..tiles) {
+                    return Err(format!(
+                        "Sprite {} tile_order {:?} isn't each of its {} tiles once",
+                        id, order, tiles
+                    )
+                    .into());
+                }
+            }
+
+            if desc.tile_anim && frames < 2 {
+                return Err(
+                    format!("Sprite {} has tile_anim, but only one frame to animate", id).into(),
+                );
+            }
+
+            if desc.share_tiles && frames < 2 {
+                return Err(format!(
+                    "Sprite {} has share_tiles, but only one frame to share tiles between",
+                    id
+                )
+                .into());
+            }
+            if desc.share_tiles && desc.tile_anim {
+                return Err(format!(
+                    "Sprite {} has share_tiles and tile_anim, but tile_anim needs every frame's tiles in full",
+                    id
+                )
+                .into());
+            }
+
+            match (desc.kind, &desc.charset) {
+                (Kind::Font, None) => {
+                    return Err(format!("Sprite {} has kind: font, but no charset", id).into())
+                }
+                (Kind::Font, Some(charset)) => check_charset(&id, charset, tiles)?,
+                (_, Some(_)) => {
+                    return Err(format!("Sprite {} has a charset, but isn't kind: font", id).into())
+                }
+                (_, None) => {}
+            }
+
+            if let Some(p) = desc.obj_priority.filter(|&p| p > 3) {
+                return Err(format!(
+                    "Sprite {} has obj_priority {}, but OBJ priorities go from 0 to 3",
+                    id, p
+                )
+                .into());
+            }
+
+            self.recolor(&id, &mut sprite_bmp, &desc.recolor);
+            self.sprites.insert(
+                id,
+                ExtractedSprite {
+                    bmp: sprite_bmp,
+                    frames,
+                    durations: desc.durations,
+                    compression: desc.compress,
+                    group: sprites.group.clone(),
+                    bpp: desc.bpp,
+                    priority: sprites.priority,
+                    obj_priority: desc.obj_priority,
+                    z: desc.z,
+                    tags: desc.tags,
+                    palbank: desc.palbank,
+                    tile_order: desc.tile_order,
+                    tile_anim: desc.tile_anim,
+                    share_tiles: desc.share_tiles,
+                    kind: desc.kind,
+                    charset: desc.charset,
+                    offset,
+                    sheet_frames,
+                },
+            );
+        }
+
+        if !sprites.allow_overlaps {
+            self.warn_overlaps(&rects);
+        }
+        Ok(covered)
+    }
+
+    fn is_defined(&self, id: &str) -> bool {
+        self.sprites.contains_key(id)
+            || self.bitmaps.contains_key(id)
+            || self.nineslices.contains_key(id)
+    }
+
+    /// Warns about opaque pixels of `bmp` outside every rect in `covered`, which may be sprites
+    /// missing from its descriptor.
+    fn warn_unused_regions(&mut self, path: &Path, bmp: &Bitmap<RGBA>, covered: &[Rect]) {
+        let mut unused = bmp.buffer.iter().map(|p| p.a != 0).collect::<Vec<_>>();
+        let opaque = unused.iter().filter(|&&u| u).count();
+        for &(x, y, w, h) in covered {
+            for row in y..y + h {
+                unused[row * bmp.width + x..row * bmp.width + x + w].fill(false);
+            }
+        }
+
+        // Flood fill each region of unused pixels to find its bounds.
+        let mut regions = Vec::new();
+        let mut count = 0;
+        for start in 0..unused.len() {
+            if !unused[start] {
+                continue;
+            }
+            unused[start] = false;
+            let (mut x0, mut y0) = (start % bmp.width, start / bmp.width);
+            let (mut x1, mut y1) = (x0, y0);
+            let mut stack = vec![start];
+            while let Some(i) = stack.pop() {
+                count += 1;
+                let (x, y) = (i % bmp.width, i / bmp.width);
+                (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+
+                let mut visit = |j: usize| {
+                    if unused[j] {
+                        unused[j] = false;
+                        stack.push(j);
+                    }
+                };
+                if x > 0 {
+                    visit(i - 1);
+                }
+                if x + 1 < bmp.width {
+                    visit(i + 1);
+                }
+                if y > 0 {
+                    visit(i - bmp.width);
+                }
+                if y + 1 < bmp.height {
+                    visit(i + bmp.width);
+                }
+            }
+            regions.push((x0, y0, x1 - x0 + 1, y1 - y0 + 1));
+        }
+
+        if regions.is_empty() {
+            return;
+        }
+        let mut listed = regions
+            .iter()
+            .take(5)
+            .map(|r| format!("{:?}", r))
+            .collect::<Vec<_>>();
+        if regions.len() > 5 {
+            listed.push(format!("{} more", regions.len() - 5));
+        }
+        self.warnings.push(format!(
+            "{} has {} opaque pixels ({:.0}% of them) outside every sprite's rect, in {}",
+            path.display(),
+            count,
+            100. * count as f64 / opaque as f64,
+            listed.join(", ")
+        ));
+    }
+
+    /// Adds the colors of a palette file a descriptor includes, leaving any color it shares with
+    /// an earlier one where that one has it.
+    fn include_palette(&mut self, path: &Path, palette: BTreeMap<u8, [u8; 3]>) -> Result<()> {
+        for (i, color) in palette {
+            if i == TRANSPARENT_INDEX {
+                return Err(format!(
+                    "Palette index {} is {:?} in {}, but OBJs always draw index {} as transparent",
+                    i,
+                    color,
+                    path.display(),
+                    TRANSPARENT_INDEX
+                )
+                .into());
+            }
+            if let Some((other, other_path)) = self.included_palette.get(&i) {
+                if *other != color {
+                    return Err(format!(
+                        "Palette index {} is {:?} in {}, but {:?} in {}",
+                        i,
+                        other,
+                        other_path.display(),
+                        color,
+                        path.display()
+                    )
+                    .into());
+                }
+                continue;
+            }
+            if self.included_palette.values().any(|(c, _)| *c == color) {
+                continue;
+            }
+            self.included_palette.insert(i, (color, path.to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Pins every color `bmp` uses to its index in the palette of the indexed PNG at `path`, and
+    /// makes the pixels of index 0 transparent, checking each color has one index to keep.
+    fn keep_indices(&mut self, path: &Path, bmp: &mut Bitmap<RGBA>) -> Result<()> {
+        let mut decoder = lodepng::Decoder::new();
+        decoder.color_convert(false);
+        let raw = match decoder.decode_file(path)? {
+            Image::RawData(raw) => raw.buffer,
+            _ => Vec::new(),
+        };
+        let palette = decoder
+            .info_png()
+            .color
+            .palette()
+            .iter()
+            .map(|c| [c.r, c.g, c.b])
+            .collect::<Vec<_>>();
+        if decoder.info_png().color.colortype() != ColorType::PALETTE || palette.is_empty() {
+            return Err(format!(
+                "{} has keep_indices, but isn't an indexed PNG",
+                path.display()
+            )
+            .into());
+        }
+
+        // The indices are packed into bytes high bits first, with no padding between rows.
+        let depth = decoder.info_png().color.bitdepth() as usize;
+        for (n, pixel) in bmp.buffer.iter_mut().enumerate() {
+            let bit = n * depth;
+            let index = raw[bit / 8] >> (8 - depth - bit % 8) & ((1 << depth) - 1) as u8;
+            if index == 0 {
+                pixel.a = 0;
+            }
+        }
+        let used = bmp
+            .buffer
+            .iter()
+            .filter(|c| c.a != 0)
+            .map(|c| [c.r, c.g, c.b])
+            .collect::<BTreeSet<_>>();
+
+        let mut indices = BTreeMap::new();
+        for (i, &color) in palette.iter().enumerate().skip(1) {
+            if !used.contains(&color) {
+                continue;
+            }
+            if let Some((other, _)) = indices.iter().find(|&(_, &c)| c == color) {
+                return Err(format!(
+                    "{} has keep_indices, but color {:?} is at both index {} and {} of its palette, so which its pixels have is lost",
+                    path.display(),
+                    color,
+                    other,
+                    i
+                )
+                .into());
+            }
+            let included = self
+                .included_palette
+                .iter()
+                .find(|&(_, (c, _))| *c == color);
+            if let Some((other, (_, other_path))) = included.filter(|&(&j, _)| j as usize != i) {
+                return Err(format!(
+                    "{} has keep_indices, but color {:?} at its index {} is at index {} in {}",
+                    path.display(),
+                    color,
+                    i,
+                    other,
+                    other_path.display()
+                )
+                .into());
+            }
+            indices.insert(i as u8, color);
+        }
+        self.include_palette(path, indices)
+    }
+
+    fn warn_overlaps(&mut self, rects: &[(String, Rect)]) {
+        for (i, (a, (ax, ay, aw, ah))) in rects.iter().enumerate() {
+            for (b, (bx, by, bw, bh)) in &rects[i + 1..] {
+                let overlap_x = ax < &(bx + bw) && bx < &(ax + aw);
+                let overlap_y = ay < &(by + bh) && by < &(ay + ah);
+                if overlap_x && overlap_y {
+                    self.warnings
+                        .push(format!("Sprites {} and {} have overlapping rects", a, b));
+                }
+            }
+        }
+    }
+
+    fn recolor(&mut self, id: &str, bmp: &mut Bitmap<RGBA>, recolor: &HashMap<[u8; 3], [u8; 3]>) {
+        let mut unused = recolor.keys().copied().collect::<BTreeSet<_>>();
+
+        // Swap every pixel in a single pass so chained recolors (a -> b, b -> c) don't compound.
+        for pixel in bmp.buffer.iter_mut().filter(|p| p.a != 0) {
+            let from = [pixel.r, pixel.g, pixel.b];
+            if let Some(&[r, g, b]) = recolor.get(&from) {
+                *pixel = RGBA::new(r, g, b, pixel.a);
+                unused.remove(&from);
+            }
+        }
+
+        for color in unused {
+            self.warnings.push(format!(
+                "Sprite {} recolors {:?}, which does not appear in it",
+                id, color
+            ));
+        }
+    }
+
+    /// Makes the `backdrop` colored pixels of `kind: bg` sprites transparent, which indexes them
+    /// as 0 like the backdrop.
+    fn use_backdrop_index(&mut self, options: &Options) {
+        let bg = self.sprites.values_mut().filter(|s| s.kind == Kind::Bg);
+        for pixel in bg.flat_map(|s| &mut s.bmp.buffer) {
+            if pixel.a != 0 && bgr555(pixel.rgb(), options.color_rounding) == options.backdrop {
+                pixel.a = 0;
+            }
+        }
+    }
+
+    /// Errors on the first opaque pixel whose color BGR555 can't hold exactly.
+    fn check_exact_colors(&self) -> Result<()> {
+        let bitmaps = self.sprites.iter().map(|(id, s)| (id, &s.bmp));
+        let bitmaps = bitmaps.chain(self.bitmaps.iter().map(|(id, (_, bmp))| (id, bmp)));
+        for (id, bmp) in bitmaps {
+            let inexact = bmp
+                .buffer
+                .iter()
+                .find(|p| p.a != 0 && [p.r, p.g, p.b].iter().any(|c| c & 7 != 0));
+            if let Some(p) = inexact {
+                return Err(format!(
+                    "Sprite {} has color [{}, {}, {}], which isn't exactly a BGR555 color, as color_rounding: strict needs",
+                    id, p.r, p.g, p.b
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes every pixel fully opaque or transparent by `alpha_threshold`, in bitmaps too, whose
+    /// transparent pixels are drawn as the backdrop.
+    fn binarize_alpha(&mut self, options: &Options) {
+        let threshold = options.alpha_threshold.max(1);
+
+        let sprites = self.sprites.iter_mut().map(|(id, s)| (id, &mut s.bmp));
+        let bitmaps = self.bitmaps.iter_mut().map(|(id, (_, bmp))| (id, bmp));
+        for (id, bmp) in sprites.chain(bitmaps) {
+            let mut binarized = 0;
+            for pixel in &mut bmp.buffer {
+                if pixel.a != 0 && pixel.a != 255 {
+                    binarized += 1;
+                    pixel.a = if pixel.a >= threshold { 255 } else { 0 };
+                }
+            }
+
+            if binarized > 0 {
+                self.warnings.push(format!(
+                    "Sprite {} has {} semi-transparent pixels, made opaque or transparent by alpha_threshold {}",
+                    id, binarized, options.alpha_threshold
+                ));
+            }
+        }
+    }
+
+    fn compile(mut self, options: &Options) -> Result<CompiledSprites> {
+        if let Some(prefix) = options.prefix.as_deref().filter(|prefix| !is_ident(prefix)) {
+            return Err(format!(
+                "prefix {:?} must be a Rust identifier, as it starts the emitted names",
+                prefix
+            )
+            .into());
+        }
+        if options.tile_size == 0 || !options.tile_size.is_multiple_of(8) {
+            return Err(format!(
+                "tile_size {} must be a multiple of the GBA's 8 pixel tiles",
+                options.tile_size
+            )
+            .into());
+        }
+        self.binarize_alpha(options);
+        if options.color_rounding == ColorRounding::Strict {
+            self.check_exact_colors()?;
+        }
+        self.use_backdrop_index(options);
+
+        let last_index = match options.max_colors {
+            None => 255,
+            Some(n) if n == 0 || options.palette_base as usize + n > 256 => {
+                return Err(format!(
+                    "max_colors {} must be from 1 to {}, the palette entries from palette_base {}",
+                    n,
+                    256 - options.palette_base as usize,
+                    options.palette_base
+                )
+                .into())
+            }
+            Some(n) => (options.palette_base as usize + n - 1) as u8,
+        };
+
+        let mut compiled = CompiledSprites {
+            next_palette_index: Some(options.palette_base.max(TRANSPARENT_INDEX + 1)),
+            palette_base: options.palette_base,
+            last_index,
+            palette: HashMap::new(),
+            sprites: BTreeMap::new(),
+            banks: Vec::new(),
+            first_bank: 0,
+            locked: BTreeSet::new(),
+            bitmaps: BTreeMap::new(),
+            nineslices: self.nineslices,
+            warnings: self.warnings,
+            notes: Vec::new(),
+            memory_use: None,
+        };
+        for (id, (group, bmp)) in self.bitmaps {
+            compiled
+                .bitmaps
+                .insert(id, CompiledBitmap::new(group, &bmp, options));
+        }
+        let mut pins = self
+            .included_palette
+            .iter()
+            .map(|(&i, (color, _))| (*color, i))
+            .collect::<BTreeMap<_, _>>();
+        if let Some(path) = &options.shared_palette {
+            pins.extend(read_shared_palette(Path::new(path))?);
+        }
+        pins.extend(&options.pin);
+        compiled.pin_colors(&pins)?;
+        compiled.lock_indices(&options.lock)?;
+
+        let bg = self
+            .sprites
+            .iter()
+            .filter(|(_, sprite)| sprite.kind == Kind::Bg)
+            .map(|(id, _)| id.clone())
+            .collect::<BTreeSet<_>>();
+        let (four_bpp, eight_bpp): (BTreeMap<_, _>, BTreeMap<_, _>) = self
+            .sprites
+            .into_iter()
+            .partition(|(_, sprite)| sprite.bpp.unwrap_or(options.bpp) == Bpp::Four);
+
+        if let Some((id, _)) = eight_bpp.iter().find(|(_, s)| s.palbank.is_some()) {
+            return Err(format!("Sprite {} has a palbank, but isn't 4bpp", id).into());
+        }
+        // 8bpp sprites go first, so 4bpp palette banks can start after their colors.
+        compiled.check_palette_fits(&eight_bpp)?;
+        let mut eight_bpp = eight_bpp.into_iter().collect::<Vec<_>>();
+        // Stable, so sprites of the same priority stay in order of name.
+        eight_bpp.sort_by_key(|(_, sprite)| std::cmp::Reverse(sprite.priority));
+        for (id, sprite) in eight_bpp {
+            compiled.add_sprite(id, sprite, None, options)?;
+        }
+        if !four_bpp.is_empty() {
+            // Banks are packed afresh, so the indices an included palette pins would be lost.
+            if let Some((_, path)) = self.included_palette.values().next() {
+                return Err(format!(
+                    "{} is included as a palette, but included palettes aren't supported with bpp 4",
+                    path.display()
+                )
+                .into());
+            }
+            let banks = compiled.pack_banks(&four_bpp, options)?;
+            for (id, sprite) in four_bpp {
+                let bank = banks[&id];
+                compiled.add_sprite(id, sprite, Some(bank), options)?;
+            }
+        }
+
+        compiled.check_const_names()?;
+        compiled.check_obj_vram(&bg)?;
+        if options.atlas {
+            compiled.lay_out_atlas()?;
+        }
+        compiled.warn_merged_colors(options);
+        compiled.note_aliases();
+        compiled.note_unused_entries();
+        if options.report_memory {
+            compiled.note_memory_use(&bg, options);
+        }
+        Ok(compiled)
+    }
+}
+
+/// Cuts a `kind: nineslice` sprite into its nine slices by its `[left, top, right, bottom]`
+/// insets, checking each slice is whole tiles.
+fn nine_slices(
+    id: &str,
+    bmp: &Bitmap<RGBA>,
+    [left, top, right, bottom]: [usize; 4],
+) -> Result<Vec<(&'static str, Bitmap<RGBA>)>> {
+    if [left, top, right, bottom, bmp.width, bmp.height]
+        .iter()
+        .any(|n| n % 8 != 0)
+    {
+        return Err(format!(
+            "Sprite {} is {}x{} with insets {:?}, which don't cut it into whole 8x8 tiles",
+            id,
+            bmp.width,
+            bmp.height,
+            [left, top, right, bottom]
+        )
+        .into());
+    }
+    if [left, top, right, bottom].contains(&0)
+        || left + right >= bmp.width
+        || top + bottom >= bmp.height
+    {
+        return Err(format!(
+            "Sprite {} is {}x{}, which insets {:?} don't leave all nine slices of",
+            id,
+            bmp.width,
+            bmp.height,
+            [left, top, right, bottom]
+        )
+        .into());
+    }
+
+    let columns = [
+        (0, left),
+        (left, bmp.width - left - right),
+        (bmp.width - right, right),
+    ];
+    let rows = [
+        (0, top),
+        (top, bmp.height - top - bottom),
+        (bmp.height - bottom, bottom),
+    ];
+    let mut slices = Vec::new();
+    for (names, (y, h)) in NINE_SLICES.iter().zip(rows) {
+        for (name, (x, w)) in names.iter().zip(columns) {
+            slices.push((*name, extract_rect(bmp, (x, y, w, h))));
+        }
+    }
+    Ok(slices)
+}
+
+/// Checks a font's `charset` names each of its `glyphs` once.
+fn check_charset(id: &str, charset: &str, glyphs: usize) -> Result<()> {
+    let chars = charset.chars().count();
+    if chars != glyphs {
+        return Err(format!(
+            "Sprite {} has {} glyphs, but its charset has {} characters",
+            id, glyphs, chars
+        )
+        .into());
+    }
+    let mut seen = BTreeSet::new();
+    if let Some(c) = charset.chars().find(|&c| !seen.insert(c)) {
+        return Err(format!("Sprite {} has {:?} in its charset more than once", id, c).into());
+    }
+    Ok(())
+}
+
+/// Splits `tiles` into `frames` equal frames and finds each distinct tile, returning them in
+/// order of first use along with the index of each frame's tiles into them.
+fn share_frame_tiles(tiles: &[Vec<u8>], frames: usize) -> (Vec<Vec<u8>>, Vec<Vec<u16>>) {
+    let mut shared = Vec::new();
+    let mut index_of = HashMap::new();
+    let indices = tiles
+        .chunks(tiles.len() / frames)
+        .map(|frame| {
+            frame
+                .iter()
+                .map(|tile| {
+                    *index_of.entry(tile).or_insert_with(|| {
+                        shared.push(tile.clone());
+                        shared.len() as u16 - 1
+                    })
+                })
+                .collect()
+        })
+        .collect();
+    (shared, indices)
+}
+
+/// Cuts a sprite out of `bmp`, or the `images` its parts name, also returning the rects of
+/// `bmp` it came from.
+fn extract_sprite(
+    anchors: &HashMap<String, (usize, usize)>,
+    images: &HashMap<PathBuf, Bitmap<RGBA>>,
+    origin: CoordinateOrigin,
+    id: &str,
+    sprite: &Sprite,
+    bmp: &Bitmap<RGBA>,
+) -> Result<(Bitmap<RGBA>, Vec<Rect>)> {
+    let anchor = sprite.anchor.as_deref();
+
+    let rect = match (sprite.rect, sprite.rect_pct) {
+        (rect, None) => rect,
+        (None, Some(pct)) => Some(resolve_rect_pct(id, pct, bmp)?),
+        (Some(_), Some(_)) => return Err(one_of_error(id)),
+    };
+
+    match (rect, sprite.parts.as_slice()) {
+        (Some(rect), []) => {
+            let rect = resolve_rect(anchors, origin, id, anchor, rect, bmp)?;
+            Ok((extract_rect(bmp, rect), vec![rect]))
+        }
+        (None, parts) if !parts.is_empty() => {
+            let parts = parts
+                .iter()
+                .map(|p| {
+                    let (source, anchor) = match &p.image {
+                        Some(image) => (&images[image], None),
+                        None => (bmp, anchor),
+                    };
+                    let rect = resolve_rect(anchors, origin, id, anchor, p.rect, source)?;
+                    Ok((rect, p.at, source, p.image.is_none()))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let width = parts.iter().map(|((_, _, w, _), (x, _), ..)| x + w).max();
+            let height = parts.iter().map(|((_, _, _, h), (_, y), ..)| y + h).max();
+            let mut result = Bitmap {
+                buffer: Vec::new(),
+                width: width.unwrap_or(0),
+                height: height.unwrap_or(0),
+            };
+            result.buffer = vec![RGBA::new(0, 0, 0, 0); result.width * result.height];
+
+            for &(rect, at, source, _) in &parts {
+                blit(&mut result, &extract_rect(source, rect), at);
+            }
+            if sprite.parts.iter().any(|p| p.image.is_some()) {
+                let placements = parts.iter().map(|&((_, _, w, h), at, ..)| (at, (w, h)));
+                check_stitched(id, placements, (result.width, result.height))?;
+            }
+            let sources = parts.into_iter().filter(|&(.., from_sheet)| from_sheet);
+            Ok((result, sources.map(|(rect, ..)| rect).collect()))
+        }
+        _ => Err(one_of_error(id)),
+    }
+}
+
+/// Checks that a sprite stitched from several images has its parts on whole tiles, with every
+/// tile covered.
+fn check_stitched(
+    id: &str,
+    placements: impl Iterator<Item = ((usize, usize), (usize, usize))>,
+    (width, height): (usize, usize),
+) -> Result<()> {
+    let columns = width / 8;
+    let mut covered = vec![false; columns * (height / 8)];
+    for (i, ((x, y), (w, h))) in placements.enumerate() {
+        if [x, y, w, h].iter().any(|v| v % 8 != 0) {
+            return Err(format!(
+                "Sprite {} part {} is {}x{} at ({}, {}), but parts stitched from several images must be placed on whole tiles",
+                id, i, w, h, x, y
+            )
+            .into());
+        }
+        for tile_y in y / 8..(y + h) / 8 {
+            for tile_x in x / 8..(x + w) / 8 {
+                covered[tile_y * columns + tile_x] = true;
+            }
+        }
+    }
+    match covered.iter().position(|&c| !c) {
+        Some(i) => Err(format!(
+            "Sprite {} is stitched from several images, but no part covers its tile ({}, {})",
+            id,
+            i % columns,
+            i / columns
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
+fn one_of_error(id: &str) -> Box<dyn std::error::Error> {
+    format!("Sprite {} needs exactly one of rect, rect_pct or parts", id).into()
+}
+
+/// Scales the fractional `rect_pct` to pixels of `bmp`, checking that it lands on tile
+/// boundaries.
+fn resolve_rect_pct(id: &str, pct: (f64, f64, f64, f64), bmp: &Bitmap<RGBA>) -> Result<Rect> {
+    let (x, y, w, h) = pct;
+    let scaled = [
+        x * bmp.width as f64,
+        y * bmp.height as f64,
+        w * bmp.width as f64,
+        h * bmp.height as f64,
+    ];
+
+    // Allow for the rounding in fractions like 1/3 written out in decimal.
+    let pixels = scaled.map(|v| v.round());
+    let aligned = scaled
+        .iter()
+        .zip(&pixels)
+        .all(|(v, p)| (v - p).abs() < 1e-6 && *p >= 0. && p % 8. == 0.);
+    if !aligned {
+        return Err(format!(
+            "Sprite {} rect_pct {:?} is {:?} in the {}x{} image, which isn't a whole number of tiles",
+            id, pct, scaled, bmp.width, bmp.height
+        )
+        .into());
+    }
+
+    let [x, y, w, h] = pixels.map(|p| p as usize);
+    Ok((x, y, w, h))
+}
+
+/// Applies `anchor` to `rect`, and checks that the result fits in `bmp`, turning it into a
+/// rect from the top left if it's from `origin` elsewhere.
+fn resolve_rect(
+    anchors: &HashMap<String, (usize, usize)>,
+    origin: CoordinateOrigin,
+    id: &str,
+    anchor: Option<&str>,
+    (x, y, w, h): Rect,
+    bmp: &Bitmap<RGBA>,
+) -> Result<Rect> {
+    let (base_x, base_y) = match anchor {
+        None => (0, 0),
+        Some(a) => *anchors
+            .get(a)
+            .ok_or_else(|| format!("Sprite {} references unknown anchor {}", id, a))?,
+    };
+
+    let rect = (base_x + x, base_y + y, w, h);
+    if rect.0 + w > bmp.width || rect.1 + h > bmp.height {
+        return Err(format!(
+            "Sprite {} rect {:?} extends past the edge of the {}x{} image",
+            id, rect, bmp.width, bmp.height
+        )
+        .into());
+    }
+    Ok(match origin {
+        CoordinateOrigin::TopLeft => rect,
+        CoordinateOrigin::BottomLeft => (rect.0, bmp.height - rect.1 - h, w, h),
+    })
+}
+
+/// Crops the transparent margins of `bmp`'s frames, down to the 8x8 tiles containing every pixel
+/// of any frame that `alpha_threshold` will make opaque, returning the result and the offset of its
+/// top left corner in a frame. A fully transparent sprite is left as is.
+fn trim(bmp: &Bitmap<RGBA>, frames: usize, alpha_threshold: u8) -> (Bitmap<RGBA>, (usize, usize)) {
+    let frame_width = bmp.width / frames;
+    let threshold = alpha_threshold.max(1);
+    let opaque = (0..bmp.height)
+        .flat_map(|y| (0..bmp.width).map(move |x| (x, y)))
+        .filter(|&(x, y)| bmp.buffer[y * bmp.width + x].a >= threshold)
+        .map(|(x, y)| (x % frame_width, y));
+
+    let (mut x0, mut y0, mut x1, mut y1) = (usize::MAX, usize::MAX, 0, 0);
+    for (x, y) in opaque {
+        (x0, y0) = (x0.min(x), y0.min(y));
+        (x1, y1) = (x1.max(x + 1), y1.max(y + 1));
+    }
+    if x0 == usize::MAX {
+        return (bmp.clone(), (0, 0));
+    }
+
+    let round_up = |v: usize, max: usize| (v.div_ceil(8) * 8).min(max);
+    let (x0, y0) = (x0 / 8 * 8, y0 / 8 * 8);
+    let (x1, y1) = (round_up(x1, frame_width), round_up(y1, bmp.height));
+
+    let (width, height) = (x1 - x0, y1 - y0);
+    let mut trimmed = Bitmap {
+        buffer: vec![RGBA::new(0, 0, 0, 0); width * frames * height],
+        width: width * frames,
+        height,
+    };
+    for frame in 0..frames {
+        let rect = (frame * frame_width + x0, y0, width, height);
+        blit(&mut trimmed, &extract_rect(bmp, rect), (frame * width, 0));
+    }
+    (trimmed, (x0, y0))
+}
+
+/// Draws the opaque pixels of `src` onto `dst` with its top left corner at `(x, y)`.
+fn blit(dst: &mut Bitmap<RGBA>, src: &Bitmap<RGBA>, (x, y): (usize, usize)) {
+    for src_y in 0..src.height {
+        for src_x in 0..src.width {
+            let pixel = src.buffer[src_y * src.width + src_x];
+            if pixel.a != 0 {
+                dst.buffer[(y + src_y) * dst.width + x + src_x] = pixel;
+            }
+        }
+    }
+}
+
+fn extract_rect<P: Copy>(input: &Bitmap<P>, (base_x, base_y, w, h): Rect) -> Bitmap<P> {
+    let mut buffer = Vec::with_capacity(w * h);
+    for y in base_y..(base_y + h) {
+        let start = y * input.width + base_x;
+        buffer.extend_from_slice(&input.buffer[start..start + w]);
+    }
+
+    Bitmap {
+        buffer,
+        width: w,
+        height: h,
+    }
+}
+
+#[derive(Debug)]
+struct CompiledSprites {
+    /// The next index to assign a color, or `None` once index 255 is taken. It never starts
+    /// below 1, as index 0 is transparent, so at most 255 colors fit.
+    next_palette_index: Option<u8>,
+    /// The first palette index emitted, for palettes that don't start at the beginning of
+    /// palette RAM.
+    palette_base: u8,
+    /// The last palette index colors can be given, which `max_colors` can bring below 255.
+    last_index: u8,
+    palette: HashMap<RGB<u8>, u8>,
+    /// For 4bpp sprites, the colors of each palette bank from `first_bank`, in index order after
+    /// the transparent index 0.
+    banks: Vec<Vec<RGB<u8>>>,
+    first_bank: u8,
+    /// Indices from the `lock` option, which are never assigned a color.
+    locked: BTreeSet<u8>,
+    sprites: BTreeMap<String, CompiledSprite>,
+    bitmaps: BTreeMap<String, CompiledBitmap>,
+    /// The insets of `kind: nineslice` sprites, with their group.
+    nineslices: BTreeMap<String, (Option<String>, [usize; 4])>,
+    warnings: Vec<String>,
+    /// Things worth knowing about the sprites that aren't problems, shown with `verbose`.
+    notes: Vec<String>,
+    /// With `report_memory`, how much of each memory budget the output takes.
+    memory_use: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PaletteEntry {
+    /// Index 0, which the hardware always treats as transparent.
+    Transparent,
+    Color(RGB<u8>),
+    /// An index between assigned colors that no color uses.
+    Unused,
+    /// An index the `lock` option keeps free of colors.
+    Locked,
+}
+
+impl PaletteEntry {
+    fn bgr555(self, options: &Options) -> u16 {
+        match self {
+            PaletteEntry::Transparent => options.backdrop,
+            PaletteEntry::Color(c) => bgr555(c, options.color_rounding),
+            PaletteEntry::Unused | PaletteEntry::Locked => options.palette_fill,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            PaletteEntry::Transparent => "transparent",
+            PaletteEntry::Color(_) => "color",
+            PaletteEntry::Unused => "unused",
+            PaletteEntry::Locked => "locked",
+        }
+    }
+}
+
+fn bgr555(c: RGB<u8>, rounding: ColorRounding) -> u16 {
+    let channel = |c| rounding.channel(c);
+    channel(c.r) | channel(c.g) << 5 | channel(c.b) << 10
+}
+
+/// A `kind: mode3` sprite.
+#[derive(Debug)]
+struct CompiledBitmap {
+    width: usize,
+    height: usize,
+    group: Option<String>,
+    /// The BGR555 color of every pixel, row by row.
+    pixels: Vec<u16>,
+}
+
+impl CompiledBitmap {
+    /// Converts `bmp` straight to BGR555. Mode 3 has no transparency, so transparent pixels
+    /// become the `backdrop` color.
+    fn new(group: Option<String>, bmp: &Bitmap<RGBA>, options: &Options) -> CompiledBitmap {
+        let pixels = bmp
+            .buffer
+            .iter()
+            .map(|p| match p.a {
+                0 => options.backdrop,
+                _ => bgr555(p.rgb(), options.color_rounding),
+            })
+            .collect();
+        CompiledBitmap {
+            width: bmp.width,
+            height: bmp.height,
+            group,
+            pixels,
+        }
+    }
+
+    fn write(&self, mut out: impl std::fmt::Write, id: &str) -> Result<()> {
+        writeln!(
+            out,
+            "/// A bitmap for Mode 3 of {}x{} pixels, as BGR555 colors row by row.",
+            self.width, self.height
+        )?;
+        writeln!(out, "pub const {}: &'static [u16] = &[", id.to_uppercase())?;
+        for line in self.pixels.chunks(16) {
+            let colors = line
+                .iter()
+                .map(|c| format!("0x{:04x}", c))
+                .collect::<Vec<_>>();
+            writeln!(out, "    {},", colors.join(", "))?;
+        }
+        writeln!(out, "];")?;
+        writeln!(
+            out,
+            "pub const {}_WIDTH: usize = {};",
+            id.to_uppercase(),
+            self.width
+        )?;
+        writeln!(
+            out,
+            "pub const {}_HEIGHT: usize = {};",
+            id.to_uppercase(),
+            self.height
+        )?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct CompiledSprite {
+    /// The width of a single frame.
+    width: usize,
+    height: usize,
+    frames: usize,
+    durations: Vec<u8>,
+    compression: Option<Compression>,
+    /// The module to emit this sprite in, if not the top level.
+    group: Option<String>,
+    /// For 4bpp sprites, the palette bank their tiles index into.
+    bank: Option<u8>,
+    /// Where the sprite goes in OBJ VRAM for 2D mapping, if laid out with `atlas`.
+    tile_xy: Option<(u8, u8)>,
+    /// Where the top left corner was in the sprite's rect before `trim`, if trimmed.
+    offset: Option<(usize, usize)>,
+    obj_priority: Option<u8>,
+    z: Option<u8>,
+    tags: BTreeSet<String>,
+    tile_anim: bool,
+    /// With `share_tiles`, the index in `tiles` of each tile of each frame.
+    frame_tiles: Option<Vec<Vec<u16>>>,
+    /// The index in `tiles` of each tile of each frame as drawn, row by row, whatever order
+    /// they're stored in.
+    tile_map: Vec<usize>,
+    /// The frame and tile position in it each of `tiles` came from, or `None` for padding.
+    tile_sources: Vec<Option<(usize, usize, usize)>>,
+    /// Where each frame's top left pixel is on the sheet, if cut from a single rect.
+    sheet_frames: Option<Vec<(usize, usize)>>,
+    /// For fonts, the character of each tile.
+    charset: Option<String>,
+    /// The palette index of every pixel, tile by tile.
+    tiles: Vec<Vec<u8>>,
+}
+
+impl CompiledSprite {
+    /// The path to this sprite's constant from the top level of the output.
+    fn path(&self, id: &str, options: &Options) -> String {
+        let name = prefixed(&id.to_uppercase(), options);
+        match &self.group {
+            Some(group) => format!("{}::{}", group, name),
+            None => name,
+        }
+    }
+
+    /// Guards against a 4bpp sprite indexing past its bank, which packing would otherwise
+    /// silently truncate into a different color.
+    fn check_indices(&self, id: &str) -> Result<()> {
+        let bank = match self.bank {
+            Some(bank) => bank,
+            None => return Ok(()),
+        };
+
+        match self.tiles.iter().flatten().find(|&&i| i >= 16) {
+            Some(i) => Err(format!(
+                "Sprite {} uses palette index {} of bank {}, but 4bpp tiles can only index 0 to 15",
+                id, i, bank
+            )
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    /// The color depth of this sprite's tiles, which only 4bpp sprites have a palette bank for.
+    fn bpp(&self) -> Bpp {
+        match self.bank {
+            Some(_) => Bpp::Four,
+            None => Bpp::Eight,
+        }
+    }
+
+    /// The palette index of the pixel at `(x, y)` of `frame`.
+    fn index_at(&self, frame: usize, x: usize, y: usize) -> u8 {
+        let x_tiles = self.width / 8;
+        let i = frame * x_tiles * (self.height / 8) + y / 8 * x_tiles + x / 8;
+        self.tiles[self.tile_map[i]][y % 8 * 8 + x % 8]
+    }
+
+    /// Where a tile of the sprite came from, for `debug_comments`.
+    fn describe_tile(&self, id: &str, source: Option<(usize, usize, usize)>) -> String {
+        let Some((frame, x, y)) = source else {
+            return format!("padding of sprite '{}'", id);
+        };
+        let mut description = format!("tile ({}, {})", x, y);
+        if self.frames > 1 {
+            write!(description, " of frame {}", frame).unwrap();
+        }
+        write!(description, " from sprite '{}'", id).unwrap();
+        if let Some(sheet_frames) = &self.sheet_frames {
+            let (frame_x, frame_y) = sheet_frames[frame];
+            write!(
+                description,
+                " at sheet ({}, {})",
+                frame_x + x * 8,
+                frame_y + y * 8
+            )
+            .unwrap();
+        }
+        description
+    }
+
+    /// The tile every tile of this sprite is a copy of, if there's more than one and they're
+    /// all the same.
+    fn repeated_tile(&self) -> Option<&[u8]> {
+        match self.tiles.as_slice() {
+            [first, rest @ ..] if !rest.is_empty() && rest.iter().all(|t| t == first) => {
+                Some(first)
+            }
+            _ => None,
+        }
+    }
+
+    /// Each tile as it's laid out in VRAM.
+    fn tile_bytes(&self, options: &Options) -> Vec<Vec<u8>> {
+        let pack = |p: &[u8]| match options.nibble_order {
+            NibbleOrder::LowFirst => p[0] | p[1] << 4,
+            NibbleOrder::HighFirst => p[0] << 4 | p[1],
+        };
+
+        match self.bank {
+            Some(_) => self
+                .tiles
+                .iter()
+                .map(|tile| tile.chunks_exact(2).map(pack).collect())
+                .collect(),
+            None => self.tiles.clone(),
+        }
+    }
+
+    /// The tile data as it should be stored in ROM.
+    fn data(&self, options: &Options) -> Vec<u8> {
+        let raw = self.tile_bytes(options).concat();
+        match self.compression {
+            Some(c) => c.compress(&raw),
+            None => raw,
+        }
+    }
+
+    /// The OAM shape and size bits for this sprite, if it is a size the hardware can display.
+    fn obj_shape(&self) -> Option<(u16, u16)> {
+        let shape_size = match (self.width, self.height) {
+            (8, 8) => (0, 0),
+            (16, 16) => (0, 1),
+            (32, 32) => (0, 2),
+            (64, 64) => (0, 3),
+            (16, 8) => (1, 0),
+            (32, 8) => (1, 1),
+            (32, 16) => (1, 2),
+            (64, 32) => (1, 3),
+            (8, 16) => (2, 0),
+            (8, 32) => (2, 1),
+            (16, 32) => (2, 2),
+            (32, 64) => (2, 3),
+            _ => return None,
+        };
+        Some(shape_size)
+    }
+}
+
+/// The 32 byte tile slots of OBJ VRAM.
+const OBJ_TILE_SLOTS: usize = 1024;
+
+/// OBJs never draw pixels with this palette index, so it's kept for transparent pixels and
+/// never given to a visible color.
+const TRANSPARENT_INDEX: u8 = 0;
+
+impl CompiledSprites {
+    fn palette_index(&mut self, color: RGBA) -> Result<u8> {
+        use std::collections::hash_map::Entry;
+
+        if color.a == 0 {
+            return Ok(TRANSPARENT_INDEX);
+        }
+        assert_eq!(color.a, 255);
+
+        let i = match (self.palette.entry(color.rgb()), self.next_palette_index) {
+            (Entry::Occupied(o), _) => return Ok(*o.get()),
+            (Entry::Vacant(e), Some(i)) => {
+                e.insert(i);
+                i
+            }
+            (Entry::Vacant(_), None) => {
+                return Err("Too many colors to fit into single palette".into())
+            }
+        };
+
+        self.next_palette_index = self.next_free_index(i.checked_add(1));
+        Ok(i)
+    }
+
+    /// The first index from `from` that isn't pinned to a color or locked.
+    fn next_free_index(&self, from: Option<u8>) -> Option<u8> {
+        (from?..=self.last_index).find(|&i| self.is_free(i))
+    }
+
+    fn is_free(&self, i: u8) -> bool {
+        !self.locked.contains(&i) && !self.palette.values().any(|&p| p == i)
+    }
+
+    /// Keeps `indices` from being assigned any color.
+    fn lock_indices(&mut self, indices: &[u8]) -> Result<()> {
+        for &i in indices {
+            if i == TRANSPARENT_INDEX || i < self.palette_base {
+                return Err(format!(
+                    "Palette index {} is locked, but colors are never assigned it anyway",
+                    i
+                )
+                .into());
+            }
+            if let Some((color, _)) = self.palette.iter().find(|&(_, &p)| p == i) {
+                return Err(format!(
+                    "Palette index {} is locked, but color {:?} is pinned to it",
+                    i, color
+                )
+                .into());
+            }
+            self.locked.insert(i);
+        }
+
+        self.next_palette_index = self.next_free_index(self.next_palette_index);
+        Ok(())
+    }
+
+    fn pin_colors(&mut self, pins: &BTreeMap<[u8; 3], u8>) -> Result<()> {
+        let mut pinned = HashMap::new();
+        for (&color, &i) in pins {
+            if i == TRANSPARENT_INDEX {
+                return Err(format!(
+                    "Color {:?} is pinned to palette index {}, which OBJs always draw as transparent",
+                    color, i
+                )
+                .into());
+            }
+            if i < self.palette_base {
+                return Err(format!(
+                    "Color {:?} is pinned to palette index {}, below palette_base {}",
+                    color, i, self.palette_base
+                )
+                .into());
+            }
+            if i > self.last_index {
+                return Err(format!(
+                    "Color {:?} is pinned to palette index {}, past the {} colors of max_colors",
+                    color,
+                    i,
+                    self.last_index as usize - self.palette_base as usize + 1
+                )
+                .into());
+            }
+            if let Some(other) = pinned.insert(i, color) {
+                return Err(format!(
+                    "Colors {:?} and {:?} are both pinned to palette index {}",
+                    other, color, i
+                )
+                .into());
+            }
+
+            self.palette
+                .insert(RGB::new(color[0], color[1], color[2]), i);
+        }
+
+        self.next_palette_index = self.next_free_index(self.next_palette_index);
+        Ok(())
+    }
+
+    /// Indexes and tiles `sprite`, with its pixels indexing into the 4bpp palette `bank` if
+    /// given, or the 8bpp palette otherwise.
+    fn add_sprite(
+        &mut self,
+        id: String,
+        sprite: ExtractedSprite,
+        bank: Option<u8>,
+        options: &Options,
+    ) -> Result<()> {
+        let ExtractedSprite {
+            bmp,
+            frames,
+            durations,
+            compression,
+            group,
+            offset,
+            obj_priority,
+            z,
+            tags,
+            tile_order,
+            tile_anim,
+            share_tiles,
+            charset,
+            sheet_frames,
+            ..
+        } = sprite;
+        let frame_width = bmp.width / frames;
+        let x_tiles = frame_width / 8;
+        let y_tiles = bmp.height / 8;
+        let palette_len = self.palette.len();
+
+        let block = options.tile_size / 8;
+        if block > 1
+            && (frame_width % options.tile_size != 0 || bmp.height % options.tile_size != 0)
+        {
+            return Err(format!(
+                "Sprite {} is {}x{}, which isn't whole {3}x{3} blocks of tile_size {3}",
+                id, frame_width, bmp.height, options.tile_size
+            )
+            .into());
+        }
+        // Row-major tile order is also the order the hardware reads a sprite's tiles in
+        // under 1D mapping, for every shape: a tall sprite is just one tile per row. With a
+        // bigger tile_size, it's the order of each block of tiles, and of the tiles in it.
+        let mut order = Vec::new();
+        for block_y in (0..y_tiles).step_by(block) {
+            for block_x in (0..x_tiles).step_by(block) {
+                for y_tile in block_y..block_y + block {
+                    order.extend((block_x..block_x + block).map(|x_tile| (x_tile, y_tile)));
+                }
+            }
+        }
+
+        // Each frame's tiles follow the previous frame's.
+        let mut tiles = Vec::new();
+        let mut tile_sources = Vec::new();
+        for frame in 0..frames {
+            for &(x_tile, y_tile) in &order {
+                tile_sources.push(Some((frame, x_tile, y_tile)));
+                let x = frame * frame_width + x_tile * 8;
+                let tile = extract_rect(&bmp, (x, y_tile * 8, 8, 8));
+                if options.check_4bpp {
+                    self.check_4bpp_tile(&id, (x / 8, y_tile), &tile);
+                }
+
+                let tile = tile
+                    .buffer
+                    .into_iter()
+                    .map(|c| match bank {
+                        Some(bank) => Ok(self.bank_index(bank, c)),
+                        None => self.palette_index(c),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                tiles.push(tile);
+            }
+        }
+        // Where each tile, in the order it was cut, ends up in `tiles`.
+        let mut stored = (0..tiles.len()).collect::<Vec<_>>();
+        if let Some(tile_order) = tile_order {
+            tiles = tile_order.iter().map(|&i| tiles[i].clone()).collect();
+            tile_sources = tile_order.iter().map(|&i| tile_sources[i]).collect();
+            for (s, &i) in tile_order.iter().enumerate() {
+                stored[i] = s;
+            }
+        }
+        let mut frame_tiles = None;
+        // A sprite with no whole tiles, which is already warned about, has nothing to share.
+        if share_tiles && !tiles.is_empty() {
+            let (shared, indices) = share_frame_tiles(&tiles, frames);
+            let per_frame = tiles.len() / frames;
+            for s in &mut stored {
+                *s = indices[*s / per_frame][*s % per_frame] as usize;
+            }
+            self.notes.push(format!(
+                "Sprite {} shares tiles between its frames, emitting {} of its {} tiles ({:.0}%)",
+                id,
+                shared.len(),
+                tiles.len(),
+                100. * shared.len() as f64 / tiles.len() as f64
+            ));
+            // Each shared tile is where it first appears.
+            tile_sources = shared
+                .iter()
+                .map(|s| tile_sources[tiles.iter().position(|t| t == s).unwrap()])
+                .collect();
+            tiles = shared;
+            frame_tiles = Some(indices);
+        }
+        let mut tile_map = vec![0; stored.len()];
+        for (k, &s) in stored.iter().enumerate() {
+            let (x_tile, y_tile) = order[k % order.len()];
+            tile_map[k / order.len() * order.len() + y_tile * x_tiles + x_tile] = s;
+        }
+        if let Some(align) = options.tile_align {
+            let padded = tiles.len().next_multiple_of(align.get());
+            tiles.resize(padded, vec![TRANSPARENT_INDEX; 64]);
+            tile_sources.resize(padded, None);
+        }
+        let sprite = CompiledSprite {
+            width: frame_width,
+            height: bmp.height,
+            frames,
+            durations,
+            compression,
+            group,
+            bank,
+            tile_xy: None,
+            offset,
+            obj_priority,
+            z,
+            tags,
+            tile_anim,
+            frame_tiles,
+            tile_map,
+            tile_sources,
+            sheet_frames,
+            charset,
+            tiles,
+        };
+        sprite.check_indices(&id)?;
+        match bank {
+            Some(bank) => trace!("sprite {} uses palette bank {}", id, bank),
+            None => trace!(
+                "sprite {} adds {} colors to the palette, which now has {}",
+                id,
+                self.palette.len() - palette_len,
+                self.palette.len()
+            ),
+        }
+        trace!(
+            "sprite {} has {} tiles, {} of them unique",
+            id,
+            sprite.tiles.len(),
+            sprite.tiles.iter().collect::<BTreeSet<_>>().len()
+        );
+        match sprite.tiles.concat().as_slice() {
+            [TRANSPARENT_INDEX, rest @ ..] if rest.iter().all(|&i| i == TRANSPARENT_INDEX) => self
+                .notes
+                .push(format!("Sprite {} is entirely transparent", id)),
+            [i, rest @ ..] if rest.iter().all(|p| p == i) => self
+                .notes
+                .push(format!("Sprite {} is a single solid color", id)),
+            _ => {}
+        }
+        self.sprites.insert(id, sprite);
+        Ok(())
+    }
+
+    /// Warns about colors that are different in the source images but the same once cut down
+    /// to BGR555, so look alike on the GBA.
+    fn warn_merged_colors(&mut self, options: &Options) {
+        let mut sets = vec![self.palette.keys().copied().collect::<Vec<_>>()];
+        sets.extend(self.banks.iter().cloned());
+
+        for colors in sets {
+            let mut by_bgr555 = BTreeMap::<_, Vec<_>>::new();
+            for c in colors {
+                by_bgr555
+                    .entry(bgr555(c, options.color_rounding))
+                    .or_default()
+                    .push(c);
+            }
+            for (bgr, mut colors) in by_bgr555 {
+                if colors.len() > 1 {
+                    colors.sort_by_key(|c| (c.r, c.g, c.b));
+                    self.warnings.push(format!(
+                        "Colors {} are all BGR555 0x{:04x}, so look the same on the GBA",
+                        colors
+                            .iter()
+                            .map(|c| format!("[{}, {}, {}]", c.r, c.g, c.b))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        bgr
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Each uncompressed sprite with exactly the same tiles as one before it, mapped to the id
+    /// of the first. 4bpp sprites must be in the same bank too, for their colors to match.
+    fn aliases(&self) -> BTreeMap<&str, &str> {
+        let mut first = HashMap::new();
+        let mut aliases = BTreeMap::new();
+        for (id, sprite) in &self.sprites {
+            if sprite.compression.is_some() {
+                continue;
+            }
+            match first.entry((sprite.bank, &sprite.tiles)) {
+                std::collections::hash_map::Entry::Occupied(o) => {
+                    aliases.insert(id.as_str(), *o.get());
+                }
+                std::collections::hash_map::Entry::Vacant(v) => {
+                    v.insert(id.as_str());
+                }
+            }
+        }
+        aliases
+    }
+
+    /// Notes each sprite emitted as an alias of another with the same tiles.
+    fn note_aliases(&mut self) {
+        let notes = self
+            .aliases()
+            .into_iter()
+            .map(|(alias, original)| {
+                format!(
+                    "Sprite {} has the same tiles as {}, so it's emitted as an alias of them",
+                    alias, original
+                )
+            })
+            .collect::<Vec<_>>();
+        self.notes.extend(notes);
+    }
+
+    /// Notes how much of each of the GBA's separate budgets the output takes: ROM for the tiles as
+    /// stored, palettes and bitmaps, VRAM for every tile once it's loaded, and OBJ palette RAM.
+    fn note_memory_use(&mut self, bg: &BTreeSet<String>, options: &Options) {
+        // Aliases are emitted as their original's constant, taking no ROM of their own.
+        let aliases = self.aliases();
+        let tiles = match options.palette_only {
+            true => 0,
+            false => self
+                .sprites
+                .iter()
+                .filter(|(id, _)| !aliases.contains_key(id.as_str()))
+                .map(|(_, s)| s.data(options).len())
+                .sum(),
+        };
+        let palette = self.palette_entries().len() * 2;
+        // The palette is loaded into the palette RAM of each kind of sprite that uses it.
+        let palette_ram = |in_bg: bool| match self.sprites.keys().any(|id| bg.contains(id) == in_bg)
+        {
+            true => palette,
+            false => 0,
+        };
+        let bitmaps = self
+            .bitmaps
+            .values()
+            .map(|b| b.pixels.len() * 2)
+            .sum::<usize>();
+        // With `palette_only`, no tiles are emitted to load.
+        let vram = |in_bg: bool| {
+            self.sprites
+                .iter()
+                .filter(|(id, _)| bg.contains(*id) == in_bg && !options.palette_only)
+                .map(|(_, s)| s.tile_bytes(options).concat().len())
+                .sum::<usize>()
+        };
+        let obj_vram_size = match self.bitmaps.is_empty() {
+            true => OBJ_TILE_SLOTS * 32,
+            false => OBJ_TILE_SLOTS / 2 * 32,
+        };
+
+        let mut rom = vec![
+            format!("{} of tiles", tiles),
+            format!("{} of palette", palette),
+        ];
+        if bitmaps > 0 {
+            rom.push(format!("{} of bitmaps", bitmaps));
+        }
+        let bg_vram = match vram(true) {
+            0 => String::new(),
+            n => format!(" and {} bytes of BG VRAM", n),
+        };
+        let bg_palette_ram = match palette_ram(true) {
+            0 => String::new(),
+            n => format!(" and {} of 512 bytes of BG palette RAM", n),
+        };
+        self.memory_use = Some(format!(
+            "The output takes {} bytes of ROM ({}), {} of {} bytes of OBJ VRAM{}, and {} of 512 bytes of OBJ palette RAM{}",
+            tiles + palette + bitmaps,
+            rom.join(", "),
+            vram(false),
+            obj_vram_size,
+            bg_vram,
+            palette_ram(false),
+            bg_palette_ram
+        ));
+    }
+
+    /// Notes palette entries emitted without a color, which are filled with `palette_fill`.
+    /// The padding at the end of each 4bpp bank isn't worth mentioning.
+    fn note_unused_entries(&mut self) {
+        let bank_start = match self.banks.is_empty() {
+            true => usize::MAX,
+            false => self.first_bank as usize * 16 - self.palette_base as usize,
+        };
+        let unused = self
+            .palette_entries()
+            .iter()
+            .take(bank_start)
+            .filter(|&&e| e == PaletteEntry::Unused)
+            .count();
+        if unused > 0 {
+            self.notes.push(format!(
+                "PALETTE has {} unused entries, filled with palette_fill",
+                unused
+            ));
+        }
+    }
+
+    /// Writes every assigned color to `path` by index, for later compiles to keep them at.
+    fn write_shared_palette(&self, path: &Path) -> Result<()> {
+        let palette = self
+            .palette
+            .iter()
+            .map(|(c, &i)| (i, [c.r, c.g, c.b]))
+            .collect::<BTreeMap<_, _>>();
+        let contents = serde_yaml::to_string(&palette)?;
+
+        // Leave the file alone if nothing changed, as it's included and would trigger a rebuild.
+        if std::fs::read_to_string(path).ok().as_deref() != Some(contents.as_str()) {
+            std::fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+
+    /// Errors if two sprites or bitmaps in the same module have ids that only differ by case,
+    /// which would be emitted as the same constant.
+    fn check_const_names(&self) -> Result<()> {
+        let sprites = self.sprites.iter().map(|(id, s)| (id, &s.group));
+        let bitmaps = self.bitmaps.iter().map(|(id, b)| (id, &b.group));
+        let mut names = HashMap::new();
+        for (id, group) in sprites.chain(bitmaps) {
+            let name = id.to_uppercase();
+            if let Some(other) = names.insert((group, name.clone()), id) {
+                return Err(format!(
+                    "Sprites {} and {} would both be emitted as {}",
+                    other, id, name
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Errors if the tiles of every sprite but the backgrounds in `bg`, which go in BG VRAM, are
+    /// more than OBJ VRAM holds at once. That's half as much in the bitmap modes, which take the
+    /// lower half for the framebuffer, so any Mode 3 bitmaps halve it.
+    fn check_obj_vram(&self, bg: &BTreeSet<String>) -> Result<()> {
+        let mut slots = self
+            .sprites
+            .iter()
+            .filter(|(id, _)| !bg.contains(*id))
+            .map(|(id, sprite)| {
+                // 8bpp tiles take up two slots each.
+                let per_tile = if sprite.bank.is_some() { 1 } else { 2 };
+                (id, sprite.tiles.len() * per_tile)
+            })
+            .collect::<Vec<_>>();
+        let total = slots.iter().map(|&(_, n)| n).sum::<usize>();
+        let limit = match self.bitmaps.is_empty() {
+            true => OBJ_TILE_SLOTS,
+            false => OBJ_TILE_SLOTS / 2,
+        };
+        if total <= limit {
+            return Ok(());
+        }
+
+        slots.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+        let top = slots
+            .iter()
+            .take(5)
+            .map(|(id, n)| format!("{} ({})", id, n))
+            .collect::<Vec<_>>();
+        Err(format!(
+            "The sprites need {} tile slots of OBJ VRAM, counting 8bpp tiles as two, but it only has {}{}. The biggest are {}",
+            total,
+            limit,
+            if self.bitmaps.is_empty() { "" } else { " with Mode 3 bitmaps" },
+            top.join(", ")
+        )
+        .into())
+    }
+
+    /// Places every sprite in OBJ VRAM for 2D mapping, with all of its frames side by side.
+    fn lay_out_atlas(&mut self) -> Result<()> {
+        let blocks = self
+            .sprites
+            .values()
+            .map(|sprite| {
+                // 8bpp tiles take up two slots each.
+                let slots = if sprite.bank.is_some() { 1 } else { 2 };
+                (sprite.width / 8 * sprite.frames * slots, sprite.height / 8)
+            })
+            .collect::<Vec<_>>();
+
+        // A block wider than a row would wrap onto the next, which 2D mapping doesn't draw.
+        for ((id, sprite), &(w, h)) in self.sprites.iter().zip(&blocks) {
+            if w > atlas::GRID_SIZE {
+                let mut counting = Vec::new();
+                if sprite.bank.is_none() {
+                    counting.push("8bpp tiles as two".to_string());
+                }
+                if sprite.frames > 1 {
+                    counting.push(format!("its {} frames side by side", sprite.frames));
+                }
+                return Err(format!(
+                    "Sprite {} is {} tile slots wide{}, so it would wrap the {} slot rows of 2D mapped OBJ VRAM",
+                    id,
+                    w,
+                    match counting.is_empty() {
+                        true => String::new(),
+                        false => format!(", counting {}", counting.join(" and ")),
+                    },
+                    atlas::GRID_SIZE
+                )
+                .into());
+            }
+            if h > atlas::GRID_SIZE {
+                return Err(format!(
+                    "Sprite {} is {} tiles tall, more than the {} slot rows of 2D mapped OBJ VRAM",
+                    id,
+                    h,
+                    atlas::GRID_SIZE
+                )
+                .into());
+            }
+        }
+
+        let positions = atlas::pack(&blocks).map_err(|i| {
+            let id = self.sprites.keys().nth(i).unwrap();
+            format!(
+                "Sprite {} needs a {}x{} block of tile slots, which doesn't fit in the {3}x{3} OBJ tile space with the other sprites",
+                id, blocks[i].0, blocks[i].1, atlas::GRID_SIZE
+            )
+        })?;
+        for (sprite, xy) in self.sprites.values_mut().zip(positions) {
+            sprite.tile_xy = Some(xy);
+        }
+        Ok(())
+    }
+
+    /// Errors before assigning any indices if the sprites have more colors than the palette has
+    /// room for, suggesting which sprites to cut colors from.
+    fn check_palette_fits(&self, sprites: &BTreeMap<String, ExtractedSprite>) -> Result<()> {
+        let sets = sprites
+            .iter()
+            .map(|(id, sprite)| (id, sprite.colors()))
+            .collect::<Vec<_>>();
+        let mut users = HashMap::<_, usize>::new();
+        for color in sets.iter().flat_map(|(_, set)| set) {
+            *users.entry(color).or_default() += 1;
+        }
+
+        let needed = users
+            .keys()
+            .filter(|c| !self.palette.contains_key(c))
+            .count();
+        let free = match self.next_palette_index {
+            Some(i) => (i..=self.last_index).filter(|&i| self.is_free(i)).count(),
+            None => 0,
+        };
+        if needed <= free {
+            return Ok(());
+        }
+
+        // Cutting a color only frees up an index if no other sprite uses it.
+        let mut own_colors = sets
+            .iter()
+            .map(|(id, set)| {
+                let own = set
+                    .iter()
+                    .filter(|c| users[c] == 1 && !self.palette.contains_key(c));
+                (id, own.count())
+            })
+            .filter(|&(_, count)| count > 0)
+            .collect::<Vec<_>>();
+        own_colors.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+
+        let mut message = format!(
+            "Too many colors to fit into single palette: {} colors need an index, but only {} are free",
+            needed, free
+        );
+        if !self.locked.is_empty() {
+            write!(message, ", with {} locked", self.locked.len())?;
+        }
+        if self.last_index < 255 {
+            write!(
+                message,
+                ", with max_colors {}",
+                self.last_index as usize - self.palette_base as usize + 1
+            )?;
+        }
+        if !own_colors.is_empty() {
+            let top = own_colors
+                .iter()
+                .take(5)
+                .map(|(id, count)| format!("{} ({})", id, count))
+                .collect::<Vec<_>>();
+            write!(
+                message,
+                ". The sprites with the most colors no other sprite uses are {}",
+                top.join(", ")
+            )?;
+        }
+        Err(message.into())
+    }
+
+    /// Packs each sprite's colors into a palette bank, returning the bank of each sprite.
+    fn pack_banks(
+        &mut self,
+        sprites: &BTreeMap<String, ExtractedSprite>,
+        options: &Options,
+    ) -> Result<BTreeMap<String, u8>> {
+        if !options.lock.is_empty() {
+            return Err("lock isn't supported with bpp 4".into());
+        }
+        // Only banks wholly within max_colors can be used.
+        let end_bank = (self.last_index as usize + 1) / 16;
+        let first_bank = match self.palette.values().max() {
+            // Banks share palette RAM with 8bpp colors, so start after them. Pinned colors are
+            // among them, so pins never land in a bank.
+            Some(&last) => last / 16 + 1,
+            None if !options.palette_base.is_multiple_of(16) => {
+                return Err(format!(
+                    "palette_base {} must be a multiple of 16 with bpp 4",
+                    options.palette_base
+                )
+                .into())
+            }
+            None => options.palette_base / 16,
+        };
+
+        // Sprites with a palbank fill their bank first, and the rest are packed around them.
+        let mut fixed = BTreeMap::<u8, (Vec<&str>, banks::Colors)>::new();
+        let mut sets = Vec::new();
+        for (id, sprite) in sprites {
+            let colors = sprite.colors();
+            if colors.len() > banks::BANK_COLORS {
+                return Err(format!(
+                    "Sprite {} has {} colors, but a 4bpp palette bank holds at most {}",
+                    id,
+                    colors.len(),
+                    banks::BANK_COLORS
+                )
+                .into());
+            }
+            match sprite.palbank {
+                Some(bank) if bank < first_bank || bank as usize >= end_bank => {
+                    return Err(format!(
+                        "Sprite {} has palbank {}, but only banks {} to {} are free",
+                        id,
+                        bank,
+                        first_bank,
+                        end_bank as isize - 1
+                    )
+                    .into())
+                }
+                Some(bank) => {
+                    let (ids, bank_colors) = fixed.entry(bank).or_default();
+                    ids.push(id);
+                    bank_colors.extend(colors);
+                }
+                None => sets.push(colors),
+            }
+        }
+        for (bank, (ids, colors)) in &fixed {
+            if colors.len() > banks::BANK_COLORS {
+                return Err(format!(
+                    "Sprites {} have palbank {}, but need {} colors between them, and a bank holds at most {}",
+                    ids.join(", "),
+                    bank,
+                    colors.len(),
+                    banks::BANK_COLORS
+                )
+                .into());
+            }
+        }
+
+        let fixed_colors = fixed.values().map(|(_, c)| c.clone()).collect::<Vec<_>>();
+        let (banks, assignment, optimal) = banks::pack(&fixed_colors, &sets);
+        self.notes.push(format!(
+            "4bpp sprites use {} palette bank{}{}",
+            banks.len(),
+            if banks.len() == 1 { "" } else { "s" },
+            if optimal {
+                ""
+            } else {
+                ", the fewest found before giving up the search"
+            }
+        ));
+        if banks.len() > end_bank.saturating_sub(first_bank as usize) {
+            return Err(format!(
+                "4bpp sprites need {} palette banks, but only {} are free from bank {}",
+                banks.len(),
+                end_bank.saturating_sub(first_bank as usize),
+                first_bank
+            )
+            .into());
+        }
+
+        // The fixed banks keep their numbers, and the packed ones take the free numbers between.
+        let mut numbers = fixed.keys().copied().collect::<Vec<_>>();
+        let mut free = (first_bank..end_bank as u8).filter(|b| !fixed.contains_key(b));
+        numbers.extend(free.by_ref().take(banks.len() - fixed.len()));
+
+        self.first_bank = first_bank;
+        let last = numbers.iter().max().map_or(0, |&b| b - first_bank + 1);
+        self.banks = vec![Vec::new(); last as usize];
+        for (bank, &number) in banks.into_iter().zip(&numbers) {
+            self.banks[(number - first_bank) as usize] = bank.into_iter().collect();
+        }
+
+        let mut assignment = assignment.into_iter();
+        Ok(sprites
+            .iter()
+            .map(|(id, sprite)| {
+                let bank = sprite
+                    .palbank
+                    .unwrap_or_else(|| numbers[assignment.next().unwrap()]);
+                (id.clone(), bank)
+            })
+            .collect())
+    }
+
+    /// The index of `color` within the 4bpp palette `bank`.
+    fn bank_index(&self, bank: u8, color: RGBA) -> u8 {
+        if color.a == 0 {
+            return TRANSPARENT_INDEX;
+        }
+
+        let colors = &self.banks[(bank - self.first_bank) as usize];
+        let i = colors.iter().position(|&c| c == color.rgb());
+        i.expect("Color missing from its sprite's bank") as u8 + 1
+    }
+
+    fn check_4bpp_tile(&mut self, id: &str, (x, y): (usize, usize), tile: &Bitmap<RGBA>) {
+        let colors = tile
+            .buffer
+            .iter()
+            .filter(|c| c.a != 0)
+            .map(|c| c.rgb())
+            .collect::<BTreeSet<_>>();
+
+        if colors.len() > 15 {
+            self.warnings.push(format!(
+                "Sprite {} tile ({}, {}) has {} colors, but 4bpp tiles fit at most 15",
+                id,
+                x,
+                y,
+                colors.len()
+            ));
+        }
+    }
+
+    /// The palette from `palette_base` up to the last assigned index, or every index of every
+    /// bank for 4bpp output.
+    fn palette_entries(&self) -> Vec<PaletteEntry> {
+        let mut colors = self
+            .palette
+            .iter()
+            .map(|(&c, &i)| (i, c))
+            .collect::<HashMap<_, _>>();
+
+        // Locked indices past the last color are included too, for code that animates them.
+        let last_locked = self.locked.iter().next_back().copied();
+        let mut entries = Vec::new();
+        for i in self.palette_base..=255 {
+            if colors.is_empty() && last_locked.is_none_or(|l| i > l) {
+                break;
+            }
+            entries.push(match colors.remove(&i) {
+                Some(c) => PaletteEntry::Color(c),
+                None if i == TRANSPARENT_INDEX => PaletteEntry::Transparent,
+                None if self.locked.contains(&i) => PaletteEntry::Locked,
+                None => PaletteEntry::Unused,
+            });
+        }
+
+        if !self.banks.is_empty() {
+            let bank_start = self.first_bank as usize * 16 - self.palette_base as usize;
+            entries.resize(bank_start, PaletteEntry::Unused);
+            entries.extend(self.banks.iter().flat_map(|bank| {
+                std::iter::once(PaletteEntry::Transparent)
+                    .chain(bank.iter().map(|&c| PaletteEntry::Color(c)))
+                    .chain(std::iter::repeat(PaletteEntry::Unused))
+                    .take(16)
+            }));
+        }
+        entries
+    }
+
+    /// Each entry of `palette_entries` with its index in palette RAM, for writers of other
+    /// output formats.
+    pub(crate) fn palette_iter(&self) -> impl Iterator<Item = (usize, PaletteEntry)> {
+        let base = self.palette_base as usize;
+        self.palette_entries()
+            .into_iter()
+            .enumerate()
+            .map(move |(i, entry)| (base + i, entry))
+    }
+
+    /// Each sprite's id and uncompressed tiles as they're laid out in VRAM, in id order, for
+    /// writers of other output formats.
+    pub(crate) fn tiles_iter<'a>(
+        &'a self,
+        options: &'a Options,
+    ) -> impl Iterator<Item = (&'a str, Vec<Vec<u8>>)> {
+        self.sprites
+            .iter()
+            .map(move |(id, sprite)| (id.as_str(), sprite.tile_bytes(options)))
+    }
+
+    fn write_to(&self, mut out: impl std::fmt::Write, options: &Options) -> Result<()> {
+        match options.prefix {
+            // Importing `Color` by name again would collide with another prefixed compile's
+            // import, but glob imports of the same item don't.
+            Some(_) => writeln!(out, "use ::gba::mmio_types::*;\n")?,
+            None => writeln!(out, "use ::gba::mmio_types::Color;\n")?,
+        }
+
+        writeln!(
+            out,
+            "pub const {}: usize = {};",
+            prefixed("PALETTE_BASE", options),
+            self.palette_base
+        )?;
+        let entries = self.palette_iter().map(|(_, e)| e).collect::<Vec<_>>();
+        let palette = prefixed("PALETTE", options);
+        writeln!(out, "pub const {}: &'static [Color] = &[", palette)?;
+        for &entry in &entries {
+            write_color(&mut out, "    ", entry, options)?;
+        }
+        writeln!(out, "];\n")?;
+        if options.aligned_palette {
+            let ty = prefixed("Palette", options);
+            let colors = entries
+                .iter()
+                .map(|e| format!("0x{:04x}", e.bgr555(options)))
+                .collect::<Vec<_>>();
+            writeln!(
+                out,
+                "/// `{palette}` as BGR555 halfwords, word aligned so it can be copied a word at a time.\n\
+                 #[repr(align(4))]\n\
+                 pub struct {ty}(pub [u16; {len}]);\n\
+                 pub const {palette}_ALIGNED: {ty} = {ty}([",
+                palette = palette,
+                ty = ty,
+                len = colors.len()
+            )?;
+            for line in colors.chunks(8) {
+                writeln!(out, "    {},", line.join(", "))?;
+            }
+            writeln!(out, "]);\n")?;
+        }
+
+        if !self.banks.is_empty() {
+            let bank_start = self.first_bank as usize * 16 - self.palette_base as usize;
+            writeln!(
+                out,
+                "/// Each palette bank in `{}`, from OBJ palette bank {}, for uploading one at a time.\n\
+                 pub const {}: [[Color; 16]; {}] = [",
+                palette,
+                self.first_bank,
+                prefixed("BANK_PALETTES", options),
+                self.banks.len()
+            )?;
+            for bank in entries[bank_start..].chunks_exact(16) {
+                writeln!(out, "    [")?;
+                for &entry in bank {
+                    write_color(&mut out, "        ", entry, options)?;
+                }
+                writeln!(out, "    ],")?;
+            }
+            writeln!(out, "];\n")?;
+        }
+
+        if options.palette_only {
+            return Ok(());
+        }
+
+        writeln!(
+            out,
+            "/// Dimensions and OAM shape of a sprite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct {} {{
+    pub width: u16,
+    pub height: u16,
+    /// Attribute 0 bits 14-15: 0 for square, 1 for wide, 2 for tall.
+    pub shape: u16,
+    /// Attribute 1 bits 14-15.
+    pub size: u16,
+}}
+",
+            prefixed("SpriteMeta", options)
+        )?;
+
+        if options.sprite_data {
+            writeln!(
+                out,
+                "/// A sprite's tiles, with the palette they index and where in OBJ palette RAM it goes.
+#[derive(Clone, Copy, Debug)]
+pub struct {}<T: 'static> {{
+    pub palette: &'static [Color],
+    /// The OBJ palette RAM index to upload `palette` at.
+    pub palette_base: usize,
+    pub tiles: &'static [T],
+    pub width: u16,
+    pub height: u16,
+}}
+",
+                prefixed("SpriteData", options)
+            )?;
+        }
+
+        let mut groups = BTreeMap::<_, Vec<_>>::new();
+        for (id, sprite) in &self.sprites {
+            groups.entry(&sprite.group).or_default().push((id, sprite));
+        }
+        // Keep sprites sharing a 4bpp palette bank next to each other.
+        for sprites in groups.values_mut() {
+            sprites.sort_by_key(|(_, sprite)| sprite.bank);
+        }
+
+        let mut bitmaps = BTreeMap::<_, Vec<_>>::new();
+        for (id, bitmap) in &self.bitmaps {
+            groups.entry(&bitmap.group).or_default();
+            bitmaps.entry(&bitmap.group).or_default().push((id, bitmap));
+        }
+
+        let aliases = self.aliases();
+        // Once any sprite has a z, every sprite gets one.
+        let any_z = self.sprites.values().any(|s| s.z.is_some());
+        // Ungrouped sprites come first, as `None` sorts before any group.
+        for (group, sprites) in groups {
+            let mut body = String::new();
+            for (id, sprite) in sprites {
+                let alias_of = aliases
+                    .get(id.as_str())
+                    .map(|&original| (original, &self.sprites[original]));
+                self.write_sprite(&mut body, id, sprite, alias_of, any_z, options)?;
+            }
+            for (id, bitmap) in bitmaps.remove(group).unwrap_or_default() {
+                bitmap.write(&mut body, &prefixed(&id.to_uppercase(), options))?;
+            }
+            for (id, (_, insets)) in self.nineslices.iter().filter(|(_, (g, _))| g == group) {
+                let const_name = prefixed(&id.to_uppercase(), options);
+                writeln!(
+                    body,
+                    "/// The left, top, right and bottom insets in pixels of {0}'s slices, `{0}_TOP_LEFT`
+/// to `{0}_BOTTOM_RIGHT`, which the edges and center stretch between.
+pub const {0}_INSETS: (u16, u16, u16, u16) = ({1}, {2}, {3}, {4});
+",
+                    const_name, insets[0], insets[1], insets[2], insets[3]
+                )?;
+            }
+
+            let group = match group {
+                None => {
+                    out.write_str(&body)?;
+                    continue;
+                }
+                Some(g) => g,
+            };
+
+            writeln!(out, "\npub mod {} {{\n    use super::*;\n", group)?;
+            for line in body.lines() {
+                if line.is_empty() {
+                    writeln!(out)?;
+                } else {
+                    writeln!(out, "    {}", line)?;
+                }
+            }
+            writeln!(out, "}}")?;
+        }
+
+        self.write_sprite_index(&mut out, options)?;
+        self.write_tags(&mut out, options)?;
+        if !self.banks.is_empty() {
+            self.write_banks(&mut out, options)?;
+        }
+
+        if options.loaders {
+            write_upload_fns(&mut out, options)?;
+        }
+        if options.emit_tests {
+            self.write_tests(&mut out, options)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a test module checking the palette fits in palette RAM, and each sprite has the
+    /// tiles it was compiled with, none of them using an index past the palette.
+    fn write_tests(&self, mut out: impl std::fmt::Write, options: &Options) -> Result<()> {
+        let base = prefixed("PALETTE_BASE", options);
+        let palette = prefixed("PALETTE", options);
+        writeln!(
+            out,
+            "\n#[cfg(test)]\nmod {} {{\n    use super::*;\n",
+            prefixed("spriter_tests", options)
+        )?;
+        writeln!(
+            out,
+            "    #[test]
+    fn palette_fits() {{
+        assert!({base} + {palette}.len() <= 256);
+    }}",
+            base = base,
+            palette = palette
+        )?;
+
+        // Each group's tests go in a module of the same name, so they're named after constants
+        // that are unique within it.
+        let mut groups = BTreeMap::<Option<&str>, String>::new();
+        for (id, sprite) in &self.sprites {
+            let out = groups.entry(sprite.group.as_deref()).or_default();
+            let path = sprite.path(id, options);
+            writeln!(
+                out,
+                "\n    #[test]\n    fn {}_tiles() {{",
+                prefixed(&id.to_uppercase(), options).to_lowercase()
+            )?;
+            if sprite.compression.is_some() {
+                // The indices can't be checked without decompressing.
+                writeln!(
+                    out,
+                    "        assert_eq!({}_DECOMPRESSED_SIZE, {});\n    }}",
+                    path,
+                    sprite.tile_bytes(options).concat().len()
+                )?;
+                continue;
+            }
+
+            writeln!(
+                out,
+                "        assert_eq!({}.len(), {});",
+                path,
+                sprite.tiles.len()
+            )?;
+            let bytes = match options.hex_tiles {
+                true => ".flat_map(|w| w.to_le_bytes())",
+                false => ".copied()",
+            };
+            let (indices, first) = match sprite.bank {
+                Some(bank) => (".flat_map(|b| [b & 0xf, b >> 4])", bank as usize * 16),
+                None => ("", 0),
+            };
+            writeln!(
+                out,
+                "        let colors = {base}..{base} + {palette}.len();
+        assert!({path}
+            .iter()
+            .flatten(){bytes}{indices}
+            .all(|i| i == 0 || colors.contains(&({first} + i as usize))));
+    }}",
+                base = base,
+                palette = palette,
+                path = path,
+                bytes = bytes,
+                indices = indices,
+                first = first,
+            )?;
+        }
+        for (group, tests) in groups {
+            let Some(group) = group else {
+                write!(out, "{}", tests)?;
+                continue;
+            };
+            // Skipping past the tests module, so the group's name is the group again.
+            writeln!(out, "\n    mod {} {{\n        use super::super::*;", group)?;
+            for line in tests.lines() {
+                match line {
+                    "" => writeln!(out)?,
+                    line => writeln!(out, "    {}", line)?,
+                }
+            }
+            writeln!(out, "    }}")?;
+        }
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+
+    /// Writes `sprite`'s constants, with its tiles as a reference to those of `alias_of` if
+    /// given.
+    fn write_sprite(
+        &self,
+        mut out: impl std::fmt::Write,
+        id: &str,
+        sprite: &CompiledSprite,
+        alias_of: Option<(&str, &CompiledSprite)>,
+        any_z: bool,
+        options: &Options,
+    ) -> Result<()> {
+        let const_name = prefixed(&id.to_uppercase(), options);
+        if let Some((original_id, original)) = alias_of {
+            writeln!(
+                out,
+                "/// The same tiles as `{original}`.\n\
+                 pub const {}: &'static [{}] = {original};",
+                const_name,
+                tile_type(sprite.bpp(), options),
+                original = match original.group == sprite.group {
+                    true => prefixed(&original_id.to_uppercase(), options),
+                    false => original.path(original_id, options),
+                },
+            )?;
+        } else if let Some(compression) = sprite.compression {
+            write_compressed(&mut out, id, sprite, compression, options)?;
+        } else if sprite.repeated_tile().is_some() {
+            // Spell out the tile once, rather than once per copy.
+            writeln!(
+                out,
+                "pub const {}_TILE: {} = {};",
+                const_name,
+                tile_type(sprite.bpp(), options),
+                tile_literal(&sprite.tile_bytes(options)[0], options),
+            )?;
+            writeln!(
+                out,
+                "pub const {0}: &'static [{1}] = &[{0}_TILE; {2}];",
+                const_name,
+                tile_type(sprite.bpp(), options),
+                sprite.tiles.len()
+            )?;
+        } else {
+            writeln!(
+                out,
+                "pub const {}: &'static [{}] = &[",
+                const_name,
+                tile_type(sprite.bpp(), options),
+            )?;
+
+            for (i, tile) in sprite.tile_bytes(options).iter().enumerate() {
+                if options.debug_comments {
+                    let source = sprite.tile_sources[i];
+                    writeln!(out, "    // {}", sprite.describe_tile(id, source))?;
+                }
+                write_tile(&mut out, tile, options)?;
+            }
+
+            writeln!(out, "];")?;
+        }
+
+        if options.tile_align.is_some() {
+            writeln!(
+                out,
+                "/// The tiles in `{0}`, padded with blank tiles to a multiple of `tile_align`.\n\
+                 pub const {0}_TILE_COUNT: usize = {1};",
+                const_name,
+                sprite.tiles.len()
+            )?;
+        }
+
+        if let Some(charset) = &sprite.charset {
+            writeln!(
+                out,
+                "/// The character of each glyph in `{}`.\n\
+                 pub const {}_CHARSET: &'static str = {:?};",
+                const_name, const_name, charset
+            )?;
+            // Compressed tiles can't be indexed by glyph.
+            if sprite.compression.is_none() {
+                write_glyph_fn(&mut out, id, &const_name, charset, sprite.bpp(), options)?;
+            }
+        }
+
+        // A font's glyphs aren't drawn as a single OBJ.
+        if let Some((shape, size)) = sprite.obj_shape().filter(|_| sprite.charset.is_none()) {
+            writeln!(
+                out,
+                "pub const {}_META: {meta} = {meta} {{ width: {}, height: {}, shape: {}, size: {} }};",
+                const_name,
+                sprite.width,
+                sprite.height,
+                shape,
+                size,
+                meta = prefixed("SpriteMeta", options),
+            )?;
+        }
+
+        // Compressed tiles have to be decompressed before they're of any use with a palette.
+        if options.sprite_data && sprite.compression.is_none() {
+            let (palette, palette_base) = match sprite.bank {
+                Some(bank) => (
+                    format!("&{}_PALETTE", const_name),
+                    (bank as usize * 16).to_string(),
+                ),
+                None => (
+                    format!("&{}", prefixed("PALETTE", options)),
+                    prefixed("PALETTE_BASE", options),
+                ),
+            };
+            writeln!(
+                out,
+                "pub const {const_name}_DATA: {data}<{ty}> = {data} {{ palette: {palette}, palette_base: {palette_base}, tiles: {const_name}, width: {width}, height: {height} }};",
+                const_name = const_name,
+                data = prefixed("SpriteData", options),
+                ty = tile_type(sprite.bpp(), options),
+                palette = palette,
+                palette_base = palette_base,
+                width = sprite.width,
+                height = sprite.height,
+            )?;
+        }
+
+        if let Some((x, y)) = sprite.tile_xy {
+            writeln!(
+                out,
+                "pub const {}_TILE_XY: (u8, u8) = ({}, {});",
+                const_name, x, y
+            )?;
+        }
+
+        if let Some(priority) = sprite.obj_priority {
+            writeln!(
+                out,
+                "/// Attribute 2 bits 10-11.\npub const {}_PRIORITY: u16 = {};",
+                const_name, priority
+            )?;
+        }
+
+        if any_z {
+            writeln!(
+                out,
+                "pub const {}_Z: u8 = {};",
+                const_name,
+                sprite.z.unwrap_or(0)
+            )?;
+        }
+
+        if let Some((x, y)) = sprite.offset {
+            writeln!(
+                out,
+                "pub const {}_OFFSET: (u16, u16) = ({}, {});",
+                const_name, x, y
+            )?;
+        }
+
+        if let Some(bank) = sprite.bank {
+            writeln!(out, "pub const {}_PALBANK: u16 = {};", const_name, bank)?;
+            writeln!(
+                out,
+                "pub const {}_PALETTE: [Color; 16] = {}[{}];",
+                const_name,
+                prefixed("BANK_PALETTES", options),
+                bank - self.first_bank
+            )?;
+            let used = sprite
+                .tiles
+                .iter()
+                .flatten()
+                .fold(0u16, |used, &i| used | 1 << i);
+            writeln!(
+                out,
+                "/// Bit `i` is set if the sprite's tiles use index `i` of its palette bank.\n\
+                 pub const {}_COLORS_USED: u16 = 0b{:016b};",
+                const_name, used
+            )?;
+        }
+
+        if sprite.tile_anim {
+            writeln!(
+                out,
+                "/// Animate by adding `{const_name}_ANIM_STRIDE` to the tile base for each frame.\n\
+                 pub const {const_name}_ANIM_FRAMES: usize = {frames};\n\
+                 /// The tiles in each frame, which follow one another.\n\
+                 pub const {const_name}_ANIM_STRIDE: usize = {stride};",
+                const_name = const_name,
+                frames = sprite.frames,
+                stride = sprite.width / 8 * (sprite.height / 8),
+            )?;
+        } else if sprite.frames > 1 {
+            writeln!(
+                out,
+                "pub const {}_FRAMES: usize = {};",
+                const_name, sprite.frames
+            )?;
+        }
+        if let Some(frame_tiles) = &sprite.frame_tiles {
+            writeln!(
+                out,
+                "/// The index in `{0}` of each tile of each frame, which share tiles they have in common.\n\
+                 pub const {0}_FRAME_TILES: [[u16; {1}]; {2}] = {3:?};",
+                const_name,
+                frame_tiles[0].len(),
+                frame_tiles.len(),
+                frame_tiles
+            )?;
+        }
+        if !sprite.durations.is_empty() {
+            writeln!(
+                out,
+                "pub const {}_DURATIONS: &'static [u8] = &{:?};",
+                const_name, sprite.durations
+            )?;
+        }
+
+        // Compressed sprites have to go through the BIOS decompression routines instead.
+        if options.loaders && sprite.compression.is_none() && sprite.bank.is_some() {
+            writeln!(
+                out,
+                "/// Uploads `{id}` to OBJ VRAM at OBJ tile index `tile_base`, and its palette bank to OBJ palette bank `{const_name}_PALBANK`.\n\
+                 ///\n\
+                 /// # Safety\n\
+                 /// Must run on the GBA, with `tile_base` leaving room for all of `{const_name}`.\n\
+                 pub unsafe fn {load}(tile_base: usize) {{\n    \
+                     {upload_tiles}({const_name}, tile_base);\n    \
+                     {upload_palette}(&{const_name}_PALETTE, {const_name}_PALBANK as usize * 16);\n\
+                 }}",
+                id = id.to_lowercase(),
+                const_name = const_name,
+                load = prefixed(&format!("load_{}", id.to_lowercase()), options),
+                upload_tiles = prefixed("upload_obj_tiles", options),
+                upload_palette = prefixed("upload_obj_palette", options),
+            )?;
+        } else if options.loaders && sprite.compression.is_none() {
+            writeln!(
+                out,
+                "/// Uploads `{id}` to OBJ VRAM at OBJ tile index `tile_base`, and `{palette}` to OBJ palette RAM at `{palette}_BASE`.\n\
+                 ///\n\
+                 /// # Safety\n\
+                 /// Must run on the GBA, with `tile_base` leaving room for all of `{const_name}`.\n\
+                 pub unsafe fn {load}(tile_base: usize) {{\n    \
+                     {upload_tiles}({const_name}, tile_base);\n    \
+                     {upload_palette}({palette}, {palette}_BASE);\n\
+                 }}",
+                id = id.to_lowercase(),
+                const_name = const_name,
+                palette = prefixed("PALETTE", options),
+                load = prefixed(&format!("load_{}", id.to_lowercase()), options),
+                upload_tiles = prefixed("upload_obj_tiles", options),
+                upload_palette = prefixed("upload_obj_palette", options),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the names of the sprites using each 4bpp palette bank.
+    fn write_banks(&self, mut out: impl std::fmt::Write, options: &Options) -> Result<()> {
+        let mut banks = vec![Vec::new(); self.banks.len()];
+        for (id, sprite) in &self.sprites {
+            if let Some(bank) = sprite.bank {
+                banks[(bank - self.first_bank) as usize].push(id.as_str());
+            }
+        }
+
+        writeln!(
+            out,
+            "\n/// The sprites using each palette bank, starting from OBJ palette bank {}.\n\
+             /// Bank `i`'s colors are `{}[i]`.\n\
+             pub const {}: &'static [&'static [&'static str]] = &[{}];",
+            self.first_bank,
+            prefixed("BANK_PALETTES", options),
+            prefixed("BANKS", options),
+            banks
+                .iter()
+                .map(|ids| format!("&{:?}", ids))
+                .collect::<Vec<_>>()
+                .join(", "),
+        )?;
+        Ok(())
+    }
+
+    /// Writes arrays for looking sprites up by a numeric id, their index in `ALL_SPRITES`.
+    ///
+    /// Sprites of the other color depth than the `bpp` option have a different tile type, so
+    /// go in their own `ALL_SPRITES_4BPP` or `ALL_SPRITES_8BPP` instead.
+    fn write_sprite_index(&self, mut out: impl std::fmt::Write, options: &Options) -> Result<()> {
+        writeln!(out)?;
+        self.write_sprite_table(&mut out, options.bpp, "", options)?;
+
+        let (other, suffix) = match options.bpp {
+            Bpp::Four => (Bpp::Eight, "_8BPP"),
+            Bpp::Eight => (Bpp::Four, "_4BPP"),
+        };
+        if self.sprites.values().any(|s| s.bpp() == other) {
+            self.write_sprite_table(&mut out, other, suffix, options)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a list of the tiles of the uncompressed sprites with each tag, and their names.
+    fn write_tags(&self, mut out: impl std::fmt::Write, options: &Options) -> Result<()> {
+        let mut tags = BTreeMap::<_, Vec<_>>::new();
+        for (id, sprite) in &self.sprites {
+            for tag in &sprite.tags {
+                tags.entry(tag).or_default().push((id, sprite));
+            }
+        }
+
+        for (tag, sprites) in tags {
+            // Compressed sprites have a different type, as in `ALL_SPRITES`.
+            let sprites = sprites
+                .into_iter()
+                .filter(|(_, s)| s.compression.is_none())
+                .collect::<Vec<_>>();
+            let bpp = sprites.first().map_or(options.bpp, |(_, s)| s.bpp());
+            if let Some((id, _)) = sprites.iter().find(|(_, s)| s.bpp() != bpp) {
+                return Err(format!(
+                    "Sprites {} and {} are both tagged {}, but one is 4bpp and the other 8bpp, so they can't share a list",
+                    sprites[0].0, id, tag
+                )
+                .into());
+            }
+
+            let list = prefixed(&format!("{}_SPRITES", tag.to_uppercase()), options);
+            let paths = sprites
+                .iter()
+                .map(|(id, sprite)| sprite.path(id, options))
+                .collect::<Vec<_>>();
+            let ids = sprites.iter().map(|(id, _)| id).collect::<Vec<_>>();
+            writeln!(
+                out,
+                "/// Every uncompressed sprite tagged `{}`.\n\
+                 pub const {}: &'static [&'static [{}]] = &[{}];\n\
+                 /// The name of each sprite in `{}`.\n\
+                 pub const {}: &'static [&'static str] = &{:?};",
+                tag,
+                list,
+                tile_type(bpp, options),
+                paths.join(", "),
+                list,
+                prefixed(&format!("{}_SPRITE_NAMES", tag.to_uppercase()), options),
+                ids
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_sprite_table(
+        &self,
+        mut out: impl std::fmt::Write,
+        bpp: Bpp,
+        suffix: &str,
+        options: &Options,
+    ) -> Result<()> {
+        // Compressed sprites have a different type, so can't share the array.
+        let (ids, sprites): (Vec<_>, Vec<_>) = self
+            .sprites
+            .iter()
+            .filter(|(_, s)| s.compression.is_none() && s.bpp() == bpp)
+            .unzip();
+
+        let all_sprites = prefixed(&format!("ALL_SPRITES{}", suffix), options);
+        let paths = ids
+            .iter()
+            .zip(sprites)
+            .map(|(id, sprite)| sprite.path(id, options))
+            .collect::<Vec<_>>();
+
+        for (i, id) in ids.iter().enumerate() {
+            let name = prefixed(&id.to_uppercase(), options);
+            writeln!(out, "pub const {}_ID: usize = {};", name, i)?;
+        }
+
+        writeln!(
+            out,
+            "/// Every uncompressed {}sprite, indexed by its `_ID`.\n\
+             pub const {}: &'static [&'static [{}]] = &[{}];",
+            match (suffix, bpp) {
+                ("", _) => "",
+                (_, Bpp::Four) => "4bpp ",
+                (_, Bpp::Eight) => "8bpp ",
+            },
+            all_sprites,
+            tile_type(bpp, options),
+            paths.join(", "),
+        )?;
+        writeln!(
+            out,
+            "/// The name of each sprite in `{}`.\n\
+             pub const {}: &'static [&'static str] = &{:?};",
+            all_sprites,
+            prefixed(&format!("SPRITE_NAMES{}", suffix), options),
+            ids
+        )?;
+
+        // A match rather than a lookup table, so there's nothing to build at runtime.
+        writeln!(
+            out,
+            "/// The tiles of the sprite in `{}` named `name`, if there is one.\n\
+             pub fn {}(name: &str) -> Option<&'static [{}]> {{\n    \
+                 match name {{",
+            all_sprites,
+            prefixed(&format!("sprite_by_name{}", suffix.to_lowercase()), options),
+            tile_type(bpp, options),
+        )?;
+        for (id, path) in ids.iter().zip(&paths) {
+            writeln!(out, "        {:?} => Some({}),", id, path)?;
+        }
+        writeln!(out, "        _ => None,\n    }}\n}}")?;
+        Ok(())
+    }
+}
+
+fn write_compressed(
+    mut out: impl std::fmt::Write,
+    id: &str,
+    sprite: &CompiledSprite,
+    compression: Compression,
+    options: &Options,
+) -> Result<()> {
+    let const_name = prefixed(&id.to_uppercase(), options);
+    let data = sprite.data(options);
+
+    let routine = match compression {
+        Compression::Lz77 => "LZ77UnComp",
+        Compression::Rle => "RLUnComp",
+    };
+    // As words, since the BIOS needs the source to be word aligned.
+    writeln!(
+        out,
+        "/// Compressed for the BIOS `{}Wram` and `{}Vram` routines.",
+        routine, routine
+    )?;
+    writeln!(out, "pub const {}: &'static [u32] = &[", const_name)?;
+    for line in data.chunks(32) {
+        let words = line
+            .chunks_exact(4)
+            .map(|w| format!("0x{:08x}", u32::from_le_bytes([w[0], w[1], w[2], w[3]])))
+            .collect::<Vec<_>>();
+        writeln!(out, "    {},", words.join(", "))?;
+    }
+    writeln!(out, "];")?;
+
+    writeln!(out, "pub const {}_COMPRESSED: bool = true;", const_name)?;
+    writeln!(
+        out,
+        "pub const {}_DECOMPRESSED_SIZE: usize = {};",
+        const_name,
+        sprite.tile_bytes(options).concat().len()
+    )?;
+    Ok(())
+}
+
+/// Writes a function from a character to its glyph in the font `const_name`. A charset of
+/// consecutive characters is an offset from the first, and any other is a match.
+fn write_glyph_fn(
+    mut out: impl std::fmt::Write,
+    id: &str,
+    const_name: &str,
+    charset: &str,
+    bpp: Bpp,
+    options: &Options,
+) -> Result<()> {
+    writeln!(
+        out,
+        "/// The glyph in `{}` for `c`, if the font has one.\n\
+         pub fn {}(c: char) -> Option<&'static {}> {{",
+        const_name,
+        prefixed(&format!("{}_glyph", id.to_lowercase()), options),
+        tile_type(bpp, options),
+    )?;
+
+    let chars = charset.chars().collect::<Vec<_>>();
+    // Bounded by the charset, as `tile_align` can pad the font past it.
+    let consecutive = chars.windows(2).all(|w| w[0] as u32 + 1 == w[1] as u32);
+    if consecutive {
+        writeln!(
+            out,
+            "    {}[..{}].get((c as u32).checked_sub({})? as usize)\n}}",
+            const_name,
+            chars.len(),
+            chars[0] as u32
+        )?;
+        return Ok(());
+    }
+
+    writeln!(out, "    match c {{")?;
+    for (i, c) in chars.iter().enumerate() {
+        writeln!(out, "        {:?} => Some(&{}[{}]),", c, const_name, i)?;
+    }
+    writeln!(out, "        _ => None,\n    }}\n}}")?;
+    Ok(())
+}
+
+fn write_color(
+    mut out: impl std::fmt::Write,
+    indent: &str,
+    entry: PaletteEntry,
+    options: &Options,
+) -> Result<()> {
+    let c = entry.bgr555(options);
+    write!(
+        out,
+        "{}Color::from_rgb({}, {}, {}),",
+        indent,
+        c & 0x1F,
+        c >> 5 & 0x1F,
+        c >> 10 & 0x1F,
+    )?;
+    match entry {
+        PaletteEntry::Color(_) => writeln!(out)?,
+        _ => writeln!(out, " // {}", entry.describe())?,
+    }
+    Ok(())
+}
+
+/// Whether `name` can be emitted as a Rust identifier, or part of one, as written: not a
+/// keyword, and not raw.
+fn is_ident(name: &str) -> bool {
+    !name.starts_with("r#") && syn::parse_str::<syn::Ident>(name).is_ok()
+}
+
+/// Errors on the group or first tag in `desc` that can't be emitted as the module or part of
+/// the constants it's named as.
+fn check_names(path: &Path, desc: &SpritesDesc) -> Result<()> {
+    if let Some(group) = desc.group.as_deref().filter(|group| !is_ident(group)) {
+        return Err(format!(
+            "{} has group {:?}, but groups must be Rust identifiers",
+            path.display(),
+            group
+        )
+        .into());
+    }
+    for (id, sprite) in &desc.sprites {
+        if let Some(tag) = sprite.tags.iter().find(|tag| !is_ident(tag)) {
+            return Err(format!(
+                "{} tags sprite {} {:?}, but tags must be Rust identifiers",
+                path.display(),
+                id,
+                tag
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// `name` with the `prefix` option in front, cased like `name`: `LEVEL1_HERO` for constants,
+/// `level1_load_hero` for functions and `Level1SpriteMeta` for types.
+fn prefixed(name: &str, options: &Options) -> String {
+    let prefix = match &options.prefix {
+        Some(prefix) => prefix,
+        None => return name.to_owned(),
+    };
+    if name.chars().all(|c| !c.is_lowercase()) {
+        format!("{}_{}", prefix.to_uppercase(), name)
+    } else if name.starts_with(char::is_uppercase) {
+        let camel = prefix
+            .split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                chars.next().map_or(String::new(), |first| {
+                    first.to_uppercase().chain(chars).collect()
+                })
+            })
+            .collect::<String>();
+        format!("{}{}", camel, name)
+    } else {
+        format!("{}_{}", prefix.to_lowercase(), name)
+    }
+}
+
+fn tile_type(bpp: Bpp, options: &Options) -> &'static str {
+    match (bpp, options.hex_tiles) {
+        (Bpp::Four, true) => "[u32; 8]",
+        (Bpp::Four, false) => "[u8; 32]",
+        (Bpp::Eight, true) => "[u32; 16]",
+        (Bpp::Eight, false) => "[u8; 64]",
+    }
+}
+
+fn write_tile(mut out: impl std::fmt::Write, tile: &[u8], options: &Options) -> Result<()> {
+    writeln!(out, "    {},", tile_literal(tile, options))?;
+    Ok(())
+}
+
+fn tile_literal(tile: &[u8], options: &Options) -> String {
+    if !options.hex_tiles {
+        return format!("{:?}", tile);
+    }
+
+    let words = tile
+        .chunks_exact(4)
+        .map(|w| format!("0x{:08x}", u32::from_le_bytes([w[0], w[1], w[2], w[3]])))
+        .collect::<Vec<_>>();
+    format!("[{}]", words.join(", "))
+}
+
+fn write_upload_fns(mut out: impl std::fmt::Write, options: &Options) -> Result<()> {
+    let copy_tiles = if options.hex_tiles {
+        "let mut dst = (0x0601_0000 + tile_base * 32) as *mut u32;
+    for tile in tiles {
+        for &word in tile {
+            dst.write_volatile(word);
+            dst = dst.add(1);
+        }
+    }"
+    } else {
+        "// VRAM doesn't support byte writes, so copy a halfword at a time.
+    let mut dst = (0x0601_0000 + tile_base * 32) as *mut u16;
+    for tile in tiles {
+        for pair in tile.chunks_exact(2) {
+            dst.write_volatile(u16::from_le_bytes([pair[0], pair[1]]));
+            dst = dst.add(1);
+        }
+    }"
+    };
+
+    writeln!(
+        out,
+        r#"
+/// Copies `tiles` of either color depth to OBJ VRAM, starting at OBJ tile index `tile_base`
+/// (in 32 byte units).
+///
+/// # Safety
+/// Must run on the GBA, with `tile_base` leaving room for all of `tiles`.
+pub unsafe fn {upload_tiles}<const N: usize>(tiles: &[[{word}; N]], tile_base: usize) {{
+    {copy_tiles}
+}}
+
+/// Copies `palette` to OBJ palette RAM, starting at index `base`.
+///
+/// # Safety
+/// Must run on the GBA, with `base` leaving room for all of `palette`.
+pub unsafe fn {upload_palette}(palette: &[Color], base: usize) {{
+    let dst = (0x0500_0200 as *mut Color).add(base);
+    for (i, &color) in palette.iter().enumerate() {{
+        dst.add(i).write_volatile(color);
+    }}
+}}"#,
+        word = if options.hex_tiles { "u32" } else { "u8" },
+        copy_tiles = copy_tiles,
+        upload_tiles = prefixed("upload_obj_tiles", options),
+        upload_palette = prefixed("upload_obj_palette", options),
+    )?;
+    Ok(())
 }
@@ -0,0 +1,392 @@
+use serde::{de::Error as _, Deserialize, Deserializer};
+use serde_yaml::{Mapping, Value};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+use syn::{
+    parse::{Parse, ParseStream},
+    Ident, Lit, LitStr, Token,
+};
+
+/// Project-wide defaults for every `compile!` invocation, read from the crate root. Options
+/// passed to the macro take precedence.
+pub const CONFIG_FILE: &str = "spriter.yml";
+
+/// Where the config file is for the crate in `manifest_dir`, which cargo gives macros as
+/// `CARGO_MANIFEST_DIR`. Without it, the working directory is the best guess, though for a
+/// workspace member that's the workspace root rather than the crate's.
+pub fn config_path(manifest_dir: Option<&Path>) -> PathBuf {
+    match manifest_dir {
+        Some(dir) => dir.join(CONFIG_FILE),
+        None => PathBuf::from(CONFIG_FILE),
+    }
+}
+
+/// Arguments to the `compile!` macro: the asset directory followed by any number of
+/// `key = value` options.
+pub struct Input {
+    pub dir: LitStr,
+    pub options: Options,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Options {
+    /// Emit `load_*` functions that upload each sprite to OBJ VRAM.
+    pub loaders: bool,
+    /// The palette index to start assigning colors from, so the palette can be placed at a
+    /// known offset in palette RAM. Colors never start below index 1, since OBJs draw index 0 as
+    /// transparent.
+    pub palette_base: u8,
+    /// The BGR555 color emitted for palette index 0. It's never drawn for sprites, but is the
+    /// backdrop color when loaded as the first BG palette entry.
+    pub backdrop: u16,
+    /// The BGR555 color emitted for palette indices between assigned colors.
+    pub palette_fill: u16,
+    /// Colors to always place at specific palette indices, such as ones that are palette
+    /// animated or shared with other assets. Other colors are assigned around them.
+    pub pin: BTreeMap<[u8; 3], u8>,
+    /// A file of colors by palette index that's shared by separate compiles. Colors already in
+    /// it keep their index, as if pinned, and any new colors are added to it.
+    pub shared_palette: Option<String>,
+    /// Palette indices to keep free of colors, like slots for palette animation to write to,
+    /// with colors assigned around them. The palette is emitted up to the last locked index.
+    pub lock: Vec<u8>,
+    /// Emit tiles as little-endian `u32` words in hex rather than decimal bytes, which is
+    /// shorter and quicker for rustc to parse.
+    pub hex_tiles: bool,
+    /// Warn about tiles with more colors than a 4bpp palette bank can hold.
+    pub check_4bpp: bool,
+    /// Bits per pixel of the emitted tiles, 4 or 8.
+    pub bpp: Bpp,
+    /// Lay the sprites out in OBJ VRAM for 2D mapping, emitting the tile slot each one's top left
+    /// tile goes in as `_TILE_XY`.
+    pub atlas: bool,
+    /// Which nibble of each byte of a 4bpp tile holds the left pixel of the pair.
+    pub nibble_order: NibbleOrder,
+    /// Pixels with at least this alpha are drawn opaque, and the rest are transparent, since the
+    /// hardware has no partial transparency.
+    pub alpha_threshold: u8,
+    /// Warn about, rather than reject, descriptor fields this crate doesn't use, so descriptors
+    /// can carry metadata for other tools.
+    pub allow_unknown_fields: bool,
+    /// Globs of descriptor paths, relative to the compiled directory, to skip.
+    pub exclude: Vec<String>,
+    /// The prefix for paths in the output's `include_bytes!`s, which are relative to the working
+    /// directory, to make them relative to the file calling the macro. Worked out from that
+    /// file's path by default.
+    pub include_base: Option<String>,
+    /// Also write grit-style C output to `<c_output>.h` and `<c_output>.c`, for projects that
+    /// mix Rust and C.
+    pub c_output: Option<String>,
+    /// Warn about opaque pixels of each sheet that no sprite's rect covers.
+    pub report_unused: bool,
+    /// Note how many bytes of ROM, VRAM and palette RAM the output takes, as each is its own
+    /// budget on the GBA.
+    pub report_memory: bool,
+    /// Also show notes about the sprites that aren't problems, like which were emitted as
+    /// aliases or how many palette banks were used, along with the warnings.
+    pub verbose: bool,
+    /// Print each sprite to stderr in color, to check them without an emulator.
+    pub preview: bool,
+    /// Scale down images that look like exports at a multiple of their real size, with every
+    /// pixel drawn as a solid block.
+    pub detect_scale: bool,
+    /// Compile everything to report any problems, but emit no sprites and write no files, for
+    /// checking assets in CI.
+    pub check_only: bool,
+    /// Fail on any warning, like rustc's `-D warnings`, for asset pipelines that have already
+    /// been cleaned up.
+    pub deny_warnings: bool,
+    /// Pad each sprite's tiles with blank tiles up to a multiple of this many, for DMA
+    /// transfers in fixed size chunks, and emit the padded count as `_TILE_COUNT`. Uploading
+    /// sprites one after another then keeps every tile base a multiple of it too, but each
+    /// upload writes the padding, so leave `_TILE_COUNT` tiles of room rather than the sprite's
+    /// own.
+    pub tile_align: Option<NonZeroUsize>,
+    /// How colors' 8-bit channels are cut down to the GBA's 5 bits.
+    pub color_rounding: ColorRounding,
+    /// Put this in front of every emitted name, as in `LEVEL1_HERO`, so several compiles can
+    /// share a module without their names colliding.
+    pub prefix: Option<String>,
+    /// The widest or tallest an image can be, in pixels, to guard against decoding a huge image
+    /// that was picked up by mistake.
+    pub max_image_size: usize,
+    /// Also write every sprite, as the GBA shows it, to a PNG sheet at this path, labelled with
+    /// their names, for design docs and reviews.
+    pub export_sheet: Option<String>,
+    /// Which pixels of every sheet are transparent, besides fully transparent ones, so a project
+    /// can keep to one convention. A descriptor's `transparent_is` overrides it.
+    pub global_transparent: Transparency,
+    /// Emit a `#[cfg(test)]` module checking the generated palette and tiles, so the crate
+    /// using them tests its assets along with everything else.
+    pub emit_tests: bool,
+    /// Also emit the palette as `PALETTE_ALIGNED`, a word aligned array of BGR555 halfwords,
+    /// for uploading with word-sized DMA or `CpuFastSet`.
+    pub aligned_palette: bool,
+    /// Cut sprites into blocks of this many pixels across and down, a multiple of 8, emitting
+    /// the 8x8 tiles of each block together, for metatiles. Sprites must be whole blocks. OBJs
+    /// wider than a block don't draw right from tiles in this order.
+    pub tile_size: usize,
+    /// Also emit a `_DATA` const per uncompressed sprite bundling its tiles, palette and size
+    /// in a `SpriteData`, so game code can load a sprite from one value.
+    pub sprite_data: bool,
+    /// The most palette RAM entries the palette can take from `palette_base`, counting index 0
+    /// if it's there, for projects that leave the rest of palette RAM to other code.
+    pub max_colors: Option<usize>,
+    /// Emit only the palette of every sprite's colors, and none of their tiles, for a master
+    /// palette to share between builds. With `shared_palette`, other compiles can then keep to
+    /// the palette file it writes.
+    pub palette_only: bool,
+    /// Precede each emitted tile with a comment of where on the sheet it came from, for
+    /// tracking down tile order and extraction bugs. It makes the output much bigger.
+    pub debug_comments: bool,
+
+    /// Compiles the assets again with each of these options, given as a mapping of names to
+    /// options to change, emitting each into a module of its name, like `eight_bpp::HERO`. The
+    /// assets are read once for every variant, with the options given outside `variants`.
+    #[serde(skip)]
+    pub variants: BTreeMap<String, Options>,
+
+    /// The config file these options were read from, if any.
+    #[serde(skip)]
+    pub config_file: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            loaders: false,
+            palette_base: 0,
+            backdrop: 0,
+            palette_fill: 0,
+            pin: BTreeMap::new(),
+            shared_palette: None,
+            lock: Vec::new(),
+            hex_tiles: false,
+            check_4bpp: false,
+            bpp: Bpp::default(),
+            atlas: false,
+            nibble_order: NibbleOrder::default(),
+            alpha_threshold: 128,
+            allow_unknown_fields: false,
+            exclude: Vec::new(),
+            include_base: None,
+            c_output: None,
+            report_unused: false,
+            report_memory: false,
+            verbose: false,
+            preview: false,
+            detect_scale: false,
+            check_only: false,
+            deny_warnings: false,
+            tile_align: None,
+            color_rounding: ColorRounding::default(),
+            prefix: None,
+            max_image_size: 4096,
+            export_sheet: None,
+            global_transparent: Transparency::default(),
+            emit_tests: false,
+            aligned_palette: false,
+            tile_size: 8,
+            sprite_data: false,
+            max_colors: None,
+            palette_only: false,
+            debug_comments: false,
+            variants: BTreeMap::new(),
+            config_file: None,
+        }
+    }
+}
+
+/// Bits per pixel of the emitted tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Bpp {
+    /// 16 color tiles, with each sprite's colors packed into one of the 16 palette banks.
+    Four,
+    /// 256 color tiles sharing a single palette.
+    #[default]
+    Eight,
+}
+
+impl<'de> Deserialize<'de> for Bpp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match u8::deserialize(deserializer)? {
+            4 => Ok(Bpp::Four),
+            8 => Ok(Bpp::Eight),
+            n => Err(D::Error::custom(format!("bpp must be 4 or 8, not {}", n))),
+        }
+    }
+}
+
+/// How two 4bpp pixels pack into a byte.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NibbleOrder {
+    /// The left pixel in the low nibble, as the hardware reads it.
+    #[default]
+    LowFirst,
+    /// The left pixel in the high nibble, for tools that expect that instead.
+    HighFirst,
+}
+
+/// How a sheet marks its transparent pixels.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Transparency {
+    /// Only by their alpha.
+    #[default]
+    Alpha,
+    /// As the pixels of the color at this index of an indexed PNG's own palette, like art
+    /// from tools that keep index 0 for transparency.
+    Index(u8),
+    /// As the pixels of this color, like a magenta background.
+    Color([u8; 3]),
+}
+
+/// How an 8-bit color channel becomes a 5-bit one.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorRounding {
+    /// Drop the low 3 bits, which darkens most colors slightly.
+    #[default]
+    Truncate,
+    /// Round to the nearest 5-bit value, which often looks better for gradients.
+    Round,
+    /// Error on colors with any of the low 3 bits set, for art meant to be made of BGR555
+    /// colors already.
+    Strict,
+}
+
+impl ColorRounding {
+    pub fn channel(self, c: u8) -> u16 {
+        match self {
+            ColorRounding::Truncate | ColorRounding::Strict => c as u16 >> 3,
+            ColorRounding::Round => ((c as u16 + 4) >> 3).min(31),
+        }
+    }
+}
+
+/// Reads the options in `config`, if it exists, and overrides them with `args`.
+pub fn load_options(config: &Path, args: Mapping) -> crate::Result<Options> {
+    let mut options = Mapping::new();
+
+    let config_exists = config.exists();
+    if config_exists {
+        let read = || -> crate::Result<Value> { Ok(serde_yaml::from_reader(File::open(config)?)?) };
+        let value = read().map_err(|e| format!("Reading {}: {}", config.display(), e))?;
+        match value {
+            Value::Null => {}
+            Value::Mapping(m) => options = m,
+            _ => return Err(format!("{} must be a mapping of options", config.display()).into()),
+        }
+    }
+
+    options.extend(args);
+    let variants = match options.remove(&Value::from("variants")) {
+        None => Mapping::new(),
+        Some(Value::Mapping(variants)) => variants,
+        Some(_) => return Err("variants must be a mapping of names to options".into()),
+    };
+
+    let mut parsed: Options = serde_yaml::from_value(Value::Mapping(options.clone()))?;
+    for (name, changes) in variants {
+        let name = match name {
+            Value::String(name) => name,
+            name => return Err(format!("Variant name {:?} isn't a string", name).into()),
+        };
+        let changes = match changes {
+            Value::Null => Mapping::new(),
+            Value::Mapping(changes) => changes,
+            _ => return Err(format!("Variant {} must be a mapping of options", name).into()),
+        };
+        if changes.contains_key(&Value::from("variants")) {
+            return Err(format!("Variant {} can't have variants of its own", name).into());
+        }
+
+        let mut variant = options.clone();
+        variant.extend(changes);
+        let variant = serde_yaml::from_value(Value::Mapping(variant))
+            .map_err(|e| format!("In variant {}: {}", name, e))?;
+        parsed.variants.insert(name, variant);
+    }
+
+    if config_exists {
+        parsed.config_file = Some(config.to_owned());
+    }
+    Ok(parsed)
+}
+
+impl Parse for Input {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let dir = input.parse::<LitStr>()?;
+
+        let mut args = Mapping::new();
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+
+            let key = input.parse::<Ident>()?;
+            input.parse::<Token![=]>()?;
+            args.insert(Value::String(key.to_string()), parse_value(input)?);
+        }
+
+        let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR");
+        let config = config_path(manifest_dir.as_deref().map(Path::new));
+        let options = load_options(&config, args).map_err(|e| syn::Error::new(dir.span(), e))?;
+        Ok(Input { dir, options })
+    }
+}
+
+fn parse_value(input: ParseStream) -> syn::Result<Value> {
+    if input.peek(syn::token::Bracket) {
+        let content;
+        syn::bracketed!(content in input);
+
+        let mut values = Vec::new();
+        while !content.is_empty() {
+            values.push(parse_value(&content)?);
+            if content.is_empty() {
+                break;
+            }
+            content.parse::<Token![,]>()?;
+        }
+        return Ok(Value::Sequence(values));
+    }
+
+    if input.peek(syn::token::Brace) {
+        let content;
+        syn::braced!(content in input);
+
+        let mut mapping = Mapping::new();
+        while !content.is_empty() {
+            let key = parse_value(&content)?;
+            content.parse::<Token![:]>()?;
+            mapping.insert(key, parse_value(&content)?);
+            if content.is_empty() {
+                break;
+            }
+            content.parse::<Token![,]>()?;
+        }
+        return Ok(Value::Mapping(mapping));
+    }
+
+    // A bare name is a string, as in YAML.
+    if input.peek(Ident) {
+        return Ok(Value::String(input.parse::<Ident>()?.to_string()));
+    }
+
+    let lit = input.parse::<Lit>()?;
+    Ok(match &lit {
+        Lit::Str(s) => Value::String(s.value()),
+        Lit::Int(i) => Value::Number(i.base10_parse::<u64>()?.into()),
+        Lit::Float(f) => Value::Number(f.base10_parse::<f64>()?.into()),
+        Lit::Bool(b) => Value::Bool(b.value),
+        _ => return Err(syn::Error::new(lit.span(), "Unsupported option value")),
+    })
+}
@@ -0,0 +1,65 @@
+//! Reading palettes exported by other tools, as colors by index like a `shared_palette` file.
+//!
+//! Index 0 is left out, as OBJs always draw it as transparent.
+
+use crate::Result;
+
+use std::collections::BTreeMap;
+
+/// Parses a raw `.pal` file of little-endian BGR555 colors, as dumped from palette RAM.
+pub fn parse_raw(bytes: &[u8]) -> Result<BTreeMap<u8, [u8; 3]>> {
+    if bytes.starts_with(b"RIFF") {
+        return Err("RIFF palettes aren't supported, only raw BGR555 ones".into());
+    }
+    if !bytes.len().is_multiple_of(2) || bytes.len() > 512 {
+        return Err(format!(
+            "Expected 2 bytes for each of up to 256 colors, but found {} bytes",
+            bytes.len()
+        )
+        .into());
+    }
+
+    let colors = bytes.chunks_exact(2).map(|c| {
+        let c = u16::from_le_bytes([c[0], c[1]]);
+        let channel = |shift: u16| ((c >> shift) as u8 & 0x1f) << 3;
+        [channel(0), channel(5), channel(10)]
+    });
+    Ok(colors
+        .enumerate()
+        .skip(1)
+        .map(|(i, c)| (i as u8, c))
+        .collect())
+}
+
+/// Parses an Adobe Color Table: 256 RGB colors, optionally followed by big-endian counts of
+/// the colors used and the index of the transparent one, which is left out too.
+pub fn parse_act(bytes: &[u8]) -> Result<BTreeMap<u8, [u8; 3]>> {
+    let (count, transparent) = match bytes.len() {
+        768 => (256, None),
+        772 => {
+            let count = u16::from_be_bytes([bytes[768], bytes[769]]) as usize;
+            let transparent = u16::from_be_bytes([bytes[770], bytes[771]]);
+            if count > 256 {
+                return Err(format!("Color count {} is more than 256", count).into());
+            }
+            (count, Some(transparent as usize))
+        }
+        len => {
+            return Err(format!(
+                "Expected 768 bytes, or 772 with a color count, but found {}",
+                len
+            )
+            .into())
+        }
+    };
+
+    let colors = bytes[..count * 3]
+        .chunks_exact(3)
+        .map(|c| [c[0], c[1], c[2]]);
+    Ok(colors
+        .enumerate()
+        .skip(1)
+        .filter(|&(i, _)| Some(i) != transparent)
+        .map(|(i, c)| (i as u8, c))
+        .collect())
+}
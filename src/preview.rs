@@ -0,0 +1,88 @@
+use crate::{CompiledSprite, CompiledSprites, TRANSPARENT_INDEX};
+
+use lodepng::RGB;
+use std::{collections::HashMap, fmt::Write};
+
+/// The most pixels of a sprite shown across or down, so big sprites don't flood the terminal.
+const MAX_SIZE: usize = 64;
+
+impl CompiledSprites {
+    /// Draws the first frame of each sprite with colored half blocks, two pixels to a
+    /// character, from its indexed tiles.
+    pub(crate) fn preview(&self) -> String {
+        let index_color = self.index_color();
+        let mut out = String::new();
+        for (id, sprite) in &self.sprites {
+            let color = |x, y| index_color(sprite, sprite.index_at(0, x, y));
+            preview_sprite(&mut out, id, sprite, color);
+        }
+        out
+    }
+
+    /// Looks up the source color a sprite's palette index stands for, or `None` for the
+    /// transparent index.
+    pub(crate) fn index_color(&self) -> impl Fn(&CompiledSprite, u8) -> Option<RGB<u8>> + '_ {
+        let palette = self
+            .palette
+            .iter()
+            .map(|(&c, &i)| (i, c))
+            .collect::<HashMap<_, _>>();
+
+        move |sprite, i| match sprite.bank {
+            _ if i == TRANSPARENT_INDEX => None,
+            Some(bank) => Some(self.banks[(bank - self.first_bank) as usize][i as usize - 1]),
+            None => palette.get(&i).copied(),
+        }
+    }
+}
+
+fn preview_sprite(
+    out: &mut String,
+    id: &str,
+    sprite: &CompiledSprite,
+    color: impl Fn(usize, usize) -> Option<RGB<u8>>,
+) {
+    // Pixels past the last whole tile aren't in the tiles to draw.
+    let width = (sprite.width / 8 * 8).min(MAX_SIZE);
+    let height = (sprite.height / 8 * 8).min(MAX_SIZE);
+    let cropped = sprite.width > MAX_SIZE || sprite.height > MAX_SIZE;
+    let _ = writeln!(
+        out,
+        "{} ({}x{}{}{}):",
+        id,
+        sprite.width,
+        sprite.height,
+        match sprite.frames {
+            1 => String::new(),
+            n => format!(", frame 1 of {}", n),
+        },
+        if cropped { ", cropped" } else { "" },
+    );
+
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let top = color(x, y);
+            let bottom = if y + 1 < height {
+                color(x, y + 1)
+            } else {
+                None
+            };
+            // The upper half block takes the foreground color, and the lower half the background.
+            match (top, bottom) {
+                (None, None) => out.push(' '),
+                (Some(t), None) => write_block(out, t, '▀'),
+                (None, Some(b)) => write_block(out, b, '▄'),
+                (Some(t), Some(b)) => {
+                    let _ = write!(out, "\x1b[48;2;{};{};{}m", b.r, b.g, b.b);
+                    write_block(out, t, '▀');
+                }
+            }
+        }
+        out.push('\n');
+    }
+}
+
+/// Writes a half block in color `c`, resetting the colors after.
+fn write_block(out: &mut String, c: RGB<u8>, block: char) {
+    let _ = write!(out, "\x1b[38;2;{};{};{}m{}\x1b[0m", c.r, c.g, c.b, block);
+}
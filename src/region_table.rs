@@ -0,0 +1,70 @@
+//! Reading sprite rects from a spreadsheet export, a CSV or TSV table of `name, x, y, w, h`
+//! rows, as a descriptor with just `sprites`.
+//!
+//! A first row of the column names, as spreadsheets usually start with, is skipped, as are
+//! blank lines.
+
+use crate::Result;
+
+use serde_yaml::{Mapping, Value};
+
+const COLUMNS: [&str; 5] = ["name", "x", "y", "w", "h"];
+
+/// Parses a table with columns separated by `delimiter` into a descriptor's YAML.
+pub fn parse(text: &str, delimiter: char) -> Result<Value> {
+    let mut sprites = Mapping::new();
+    for (n, line) in text.lines().enumerate() {
+        let fields = line
+            .split(delimiter)
+            .map(|f| f.trim().trim_matches('"'))
+            .collect::<Vec<_>>();
+        if fields.iter().all(|f| f.is_empty()) {
+            continue;
+        }
+        if n == 0 && fields.iter().map(|f| f.to_lowercase()).eq(COLUMNS) {
+            continue;
+        }
+
+        let line = n + 1;
+        if fields.len() != COLUMNS.len() {
+            return Err(format!(
+                "Line {}: expected {} columns of {}, but found {}",
+                line,
+                COLUMNS.len(),
+                COLUMNS.join(", "),
+                fields.len()
+            )
+            .into());
+        }
+        if fields[0].is_empty() {
+            return Err(format!("Line {}: the sprite has no name", line).into());
+        }
+        let mut rect = Vec::new();
+        for (column, field) in COLUMNS[1..].iter().zip(&fields[1..]) {
+            let value = field.parse::<u64>().map_err(|_| {
+                format!(
+                    "Line {}: {} should be a whole number, but is {:?}",
+                    line, column, field
+                )
+            })?;
+            rect.push(Value::from(value));
+        }
+
+        let mut sprite = Mapping::new();
+        sprite.insert("rect".into(), Value::Sequence(rect));
+        if sprites
+            .insert(fields[0].into(), Value::Mapping(sprite))
+            .is_some()
+        {
+            return Err(format!(
+                "Line {}: sprite {} is already in the table",
+                line, fields[0]
+            )
+            .into());
+        }
+    }
+
+    let mut desc = Mapping::new();
+    desc.insert("sprites".into(), Value::Mapping(sprites));
+    Ok(Value::Mapping(desc))
+}
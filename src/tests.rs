@@ -0,0 +1,2626 @@
+use super::*;
+
+use std::{env, fs, num::NonZeroUsize};
+
+/// Compiles `tests/fixtures/<name>` and compares the output against the fixture's
+/// `expected.rs`.
+fn check_golden(name: &str, options: &Options) {
+    let dir = format!("tests/fixtures/{}", name);
+    let actual = compile_internal(&dir, options)
+        .unwrap()
+        // Keep goldens independent of the host's path separator.
+        .replace('\\', "/");
+
+    check_golden_file(&Path::new(&dir).join("expected.rs"), &actual);
+}
+
+/// Compares `actual` against the contents of `golden`. Set `UPDATE_GOLDEN=1` to rewrite the
+/// golden files instead.
+fn check_golden_file(golden: &Path, actual: &str) {
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(golden, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(golden)
+        .unwrap_or_else(|e| panic!("Reading {}: {}", golden.display(), e));
+    assert_eq!(actual, expected, "Output differs from {}", golden.display());
+}
+
+fn compile_fixture(name: &str, options: &Options) -> Result<CompiledSprites> {
+    read_dir(&format!("tests/fixtures/{}", name), options, String::new())?.compile(options)
+}
+
+#[test]
+fn transparency() {
+    check_golden("transparency", &Options::default());
+}
+
+#[test]
+fn multi_tile() {
+    check_golden("multi_tile", &Options::default());
+}
+
+#[test]
+fn palette_overflow() {
+    let err = compile_internal("tests/fixtures/palette_overflow", &Options::default()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Too many colors to fit into single palette: 256 colors need an index, but only 255 are \
+         free. The sprites with the most colors no other sprite uses are rainbow (256)"
+    );
+}
+
+#[test]
+fn palette_holds_255_colors() {
+    // Index 0 is transparent, so 255 colors fill the palette. The last pixel is transparent.
+    let sheet_of = |colors: usize| {
+        sheet(16, 16, move |x, y| match y * 16 + x {
+            i if i < colors => RGBA::new(i as u8, 0, 0, 255),
+            _ => RGBA::new(0, 0, 0, 0),
+        })
+    };
+    let desc = "sprites: { s: { rect: [0, 0, 16, 16] } }";
+    let compiled = compile_sheet(desc, sheet_of(255), &Options::default()).unwrap();
+    assert_eq!(compiled.palette.len(), 255);
+    assert_eq!(compiled.palette.values().max(), Some(&255));
+    assert_eq!(compiled.next_palette_index, None);
+
+    let bmp = sheet(16, 16, |x, y| RGBA::new(x as u8, y as u8, 1, 255));
+    let err = compile_sheet(desc, bmp, &Options::default()).err().unwrap();
+    assert!(err
+        .to_string()
+        .starts_with("Too many colors to fit into single palette: 256 colors need an index, but only 255 are free."));
+
+    // Assigning past index 255 directly fails too, rather than wrapping to 0.
+    let mut compiled = compile_sheet(desc, sheet_of(255), &Options::default()).unwrap();
+    let err = compiled.palette_index(RGBA::new(1, 2, 3, 255)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Too many colors to fit into single palette"
+    );
+}
+
+#[test]
+fn palette_overflow_suggests_sprites() {
+    // `a` and `b` share 200 colors, and add 40 and 20 of their own. `c` adds 10.
+    let bmp = sheet(48, 16, |x, y| {
+        let i = y * 16 + x % 16;
+        let color = match (x / 16, i) {
+            (0, i) if i < 200 => i,
+            (0, i) => 200 + i % 40,
+            (1, i) if i < 200 => i,
+            (1, i) => 240 + i % 20,
+            (_, i) => 260 + i % 10,
+        };
+        RGBA::new((color % 256) as u8, (color / 256) as u8, 0, 255)
+    });
+    let desc = "
+        sprites:
+          a: { rect: [0, 0, 16, 16] }
+          b: { rect: [16, 0, 16, 16] }
+          c: { rect: [32, 0, 16, 16] }
+    ";
+    let err = compile_sheet(desc, bmp, &Options::default()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Too many colors to fit into single palette: 270 colors need an index, but only 255 are \
+         free. The sprites with the most colors no other sprite uses are a (40), b (20), c (10)"
+    );
+}
+
+/// Builds an in-memory sheet by calling `pixel` for every coordinate.
+fn sheet(width: usize, height: usize, pixel: impl Fn(usize, usize) -> RGBA) -> Bitmap<RGBA> {
+    let mut buffer = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            buffer.push(pixel(x, y));
+        }
+    }
+    Bitmap {
+        buffer,
+        width,
+        height,
+    }
+}
+
+fn compile_sheet(desc: &str, bmp: Bitmap<RGBA>, options: &Options) -> Result<CompiledSprites> {
+    let mut builder = SpritesBuilder::default();
+    builder.add(serde_yaml::from_str(desc).unwrap(), bmp, options)?;
+    builder.compile(options)
+}
+
+#[test]
+fn non_square_shapes_emit_tiles_in_1d_order() {
+    let shapes = [
+        (16, 8, 1, 0),
+        (32, 8, 1, 1),
+        (32, 16, 1, 2),
+        (64, 32, 1, 3),
+        (8, 16, 2, 0),
+        (8, 32, 2, 1),
+        (16, 32, 2, 2),
+        (32, 64, 2, 3),
+    ];
+
+    for (width, height, shape, size) in shapes {
+        // Color each tile by its row-major position, so palette indices count up in tile order.
+        let bmp = sheet(width, height, |x, y| {
+            let i = (y / 8 * (width / 8) + x / 8) as u8;
+            RGBA::new(i, 0, 0, 255)
+        });
+        let desc = format!(
+            "sprites: {{ s: {{ rect: [0, 0, {}, {}] }} }}",
+            width, height
+        );
+        let compiled = compile_sheet(&desc, bmp, &Options::default()).unwrap();
+
+        let sprite = &compiled.sprites["s"];
+        assert_eq!(
+            sprite.obj_shape(),
+            Some((shape, size)),
+            "{}x{}",
+            width,
+            height
+        );
+        assert_eq!(sprite.tiles.len(), width * height / 64);
+        for (i, tile) in sprite.tiles.iter().enumerate() {
+            assert!(
+                tile.iter().all(|&p| p as usize == i + 1),
+                "{}x{} tile {}",
+                width,
+                height,
+                i
+            );
+        }
+    }
+}
+
+#[test]
+fn palette_base() {
+    check_golden(
+        "palette_base",
+        &Options {
+            palette_base: 16,
+            ..Options::default()
+        },
+    );
+}
+
+#[test]
+fn palette_base_overflow() {
+    let bmp = sheet(8, 8, |x, _| RGBA::new(x as u8, 0, 0, 255));
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+
+    let options = Options {
+        palette_base: 248,
+        ..Options::default()
+    };
+    assert!(compile_sheet(desc, bmp.clone(), &options).is_ok());
+
+    let options = Options {
+        palette_base: 249,
+        ..Options::default()
+    };
+    assert!(compile_sheet(desc, bmp, &options).is_err());
+}
+
+#[test]
+fn hex_tiles() {
+    check_golden(
+        "hex_tiles",
+        &Options {
+            hex_tiles: true,
+            ..Options::default()
+        },
+    );
+}
+
+#[test]
+fn check_4bpp_warns_on_colorful_tiles() {
+    // The second tile has 16 colors, one too many for a 4bpp bank.
+    let bmp = sheet(16, 8, |x, y| {
+        let c = if x < 8 { 0 } else { (y * 8 + x) as u8 % 16 * 8 };
+        RGBA::new(c, 0, 0, 255)
+    });
+    let desc = "sprites: { s: { rect: [0, 0, 16, 8] } }";
+
+    let compiled = compile_sheet(desc, bmp.clone(), &Options::default()).unwrap();
+    assert_eq!(compiled.warnings, Vec::<String>::new());
+
+    let options = Options {
+        check_4bpp: true,
+        ..Options::default()
+    };
+    let compiled = compile_sheet(desc, bmp, &options).unwrap();
+    assert_eq!(
+        compiled.warnings,
+        ["Sprite s tile (1, 0) has 16 colors, but 4bpp tiles fit at most 15"]
+    );
+}
+
+#[test]
+fn silent_conversions_warn() {
+    let bmp = sheet(16, 12, |x, _| {
+        RGBA::new(if x < 8 { 255 } else { 254 }, 0, 0, 255)
+    });
+    let desc = "sprites: { s: { rect: [0, 0, 16, 12] } }";
+    let compiled = compile_sheet(desc, bmp, &Options::default()).unwrap();
+    assert_eq!(
+        compiled.warnings,
+        [
+            "Sprite s is 16x12, which isn't whole 8x8 tiles, so the pixels past its last whole tiles are dropped",
+            "Colors [254, 0, 0], [255, 0, 0] are all BGR555 0x001f, so look the same on the GBA",
+        ]
+    );
+
+    let bmp = sheet(8, 8, |x, _| {
+        RGBA::new(255, 0, if x < 4 { 0 } else { 255 }, 255)
+    });
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+    let options = Options {
+        pin: [([0, 255, 0], 4)].into_iter().collect(),
+        ..Options::default()
+    };
+    let compiled = compile_sheet(desc, bmp, &options).unwrap();
+    assert_eq!(
+        compiled.notes,
+        ["PALETTE has 1 unused entries, filled with palette_fill"]
+    );
+}
+
+#[test]
+fn glob_matching() {
+    assert!(glob::matches("*.yml", "a/b/c.yml"));
+    assert!(!glob::matches("*.yml", "a/b/c.png"));
+    assert!(glob::matches("wip/*", "wip/a.yml"));
+    assert!(!glob::matches("wip/*", "wip/deeper/a.yml"));
+    assert!(!glob::matches("wip/*", "art/wip/a.yml"));
+    assert!(glob::matches("**/wip/*", "wip/a.yml"));
+    assert!(glob::matches("**/wip/*", "art/wip/a.yml"));
+    assert!(glob::matches("art/**", "art/wip/a.yml"));
+    assert!(glob::matches("a?c.yml", "abc.yml"));
+    assert!(!glob::matches("a?c.yml", "ac.yml"));
+}
+
+#[test]
+fn config_file_with_overrides() {
+    let config = Path::new("tests/fixtures/config/spriter.yml");
+
+    let options = options::load_options(config, serde_yaml::Mapping::new()).unwrap();
+    assert_eq!(options.palette_base, 16);
+    assert!(options.hex_tiles);
+    assert_eq!(options.exclude, ["*.wip.yml"]);
+    assert_eq!(options.config_file.as_deref(), Some(config));
+
+    let args = serde_yaml::from_str("{ palette_base: 32 }").unwrap();
+    let options = options::load_options(config, args).unwrap();
+    assert_eq!(options.palette_base, 32);
+    assert!(options.hex_tiles);
+
+    let missing = Path::new("does/not/exist.yml");
+    let options = options::load_options(missing, serde_yaml::Mapping::new()).unwrap();
+    assert_eq!(options.config_file, None);
+
+    // A workspace member's config is in its own directory, not the workspace root the macro is
+    // run from.
+    let member = Path::new("tests/fixtures/config");
+    assert_eq!(options::config_path(Some(member)), config);
+    assert_eq!(options::config_path(None), Path::new("spriter.yml"));
+    let options = options::load_options(
+        &options::config_path(Some(member)),
+        serde_yaml::Mapping::new(),
+    )
+    .unwrap();
+    assert_eq!(options.palette_base, 16);
+
+    let manifest_dir = env::current_dir().unwrap().join(member);
+    let mut out = String::new();
+    use_path(
+        &mut out,
+        &Options::default(),
+        options::config_path(Some(&manifest_dir)),
+    )
+    .unwrap();
+    assert_eq!(
+        out,
+        format!(
+            "const _: &[u8] = include_bytes!(\"{}\");\n",
+            manifest_dir.join("spriter.yml").display()
+        )
+    );
+}
+
+#[test]
+fn variants() {
+    let input = syn::parse_str::<options::Input>(
+        r#""dir", hex_tiles = true, variants = { four: { bpp: 4, nibble_order: high_first }, eight: {} }"#,
+    )
+    .unwrap();
+    let variants = &input.options.variants;
+    assert_eq!(variants.keys().collect::<Vec<_>>(), ["eight", "four"]);
+    assert_eq!(variants["four"].bpp, Bpp::Four);
+    assert_eq!(variants["four"].nibble_order, NibbleOrder::HighFirst);
+    assert!(variants["four"].hex_tiles && variants["eight"].hex_tiles);
+    assert_eq!(variants["eight"].bpp, Bpp::Eight);
+
+    let out = compile_internal("tests/fixtures/solid", &input.options).unwrap();
+    assert_eq!(out.matches("include_bytes!").count(), 2);
+    let four = out.find("pub mod four {").unwrap();
+    assert!(out.find("pub mod eight {").unwrap() < four);
+    assert!(out[four..].contains("    pub const FILL_PALBANK: u16 = 0;"));
+
+    let args = serde_yaml::from_str("{ variants: { a: { variants: {} } } }").unwrap();
+    let err = options::load_options(Path::new("missing.yml"), args).unwrap_err();
+    assert_eq!(err.to_string(), "Variant a can't have variants of its own");
+}
+
+#[test]
+fn exclude() {
+    let options = Options {
+        exclude: vec!["*.wip.yml".to_string()],
+        ..Options::default()
+    };
+    check_golden("exclude", &options);
+}
+
+#[test]
+fn animation() {
+    check_golden("animation", &Options::default());
+}
+
+#[test]
+fn animation_durations_must_match_frames() {
+    let bmp = sheet(32, 8, |_, _| RGBA::new(0, 0, 0, 0));
+
+    let desc = "sprites: { s: { rect: [0, 0, 32, 8], frames: 4, durations: [1, 2] } }";
+    let err = compile_sheet(desc, bmp.clone(), &Options::default())
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "Sprite s has 2 durations for 4 frames");
+
+    let desc = "sprites: { s: { rect: [0, 0, 32, 8], frames: 3 } }";
+    let err = compile_sheet(desc, bmp, &Options::default()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite s is 32 pixels wide, which doesn't split into 3 frames"
+    );
+}
+
+#[test]
+fn overlapping_rects_warn() {
+    let bmp = sheet(24, 8, |_, _| RGBA::new(0, 0, 0, 0));
+    let sprites =
+        "{ a: { rect: [0, 0, 16, 8] }, b: { rect: [8, 0, 16, 8] }, c: { rect: [16, 0, 8, 8] } }";
+
+    let desc = format!("sprites: {}", sprites);
+    let compiled = compile_sheet(&desc, bmp.clone(), &Options::default()).unwrap();
+    assert_eq!(
+        compiled.warnings,
+        [
+            "Sprites a and b have overlapping rects",
+            "Sprites b and c have overlapping rects"
+        ]
+    );
+
+    let desc = format!("allow_overlaps: true\nsprites: {}", sprites);
+    let compiled = compile_sheet(&desc, bmp, &Options::default()).unwrap();
+    assert_eq!(compiled.warnings, Vec::<String>::new());
+}
+
+#[test]
+fn c_output() {
+    let dir = Path::new("tests/fixtures/c_output");
+    let compiled = compile_fixture("c_output", &Options::default()).unwrap();
+
+    let mut header = String::new();
+    let mut source = String::new();
+    compiled
+        .write_c_to("sprites", &Options::default(), &mut header, &mut source)
+        .unwrap();
+
+    check_golden_file(&dir.join("expected.h"), &header);
+    check_golden_file(&dir.join("expected.c"), &source);
+}
+
+#[test]
+fn custom_writer_iterators() {
+    let options = Options {
+        palette_base: 16,
+        ..Options::default()
+    };
+    let bmp = sheet(16, 8, |x, _| match x / 8 {
+        0 => RGBA::new(255, 0, 0, 255),
+        _ => RGBA::new(0, 0, 255, 255),
+    });
+    let desc = "sprites: { b: { rect: [8, 0, 8, 8] }, a: { rect: [0, 0, 8, 8] } }";
+    let compiled = compile_sheet(desc, bmp, &options).unwrap();
+
+    let palette = compiled.palette_iter().collect::<Vec<_>>();
+    assert_eq!(palette[0].0, 16);
+    assert_eq!(palette.len(), compiled.palette_entries().len());
+
+    let tiles = compiled.tiles_iter(&options).collect::<Vec<_>>();
+    assert_eq!(
+        tiles.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        ["a", "b"]
+    );
+    assert_eq!(tiles[0].1, compiled.sprites["a"].tile_bytes(&options));
+}
+
+#[test]
+fn rect_past_image_edge() {
+    let bmp = sheet(16, 8, |_, _| RGBA::new(0, 0, 0, 0));
+
+    let desc = "sprites: { s: { rect: [8, 0, 8, 8] } }";
+    assert!(compile_sheet(desc, bmp.clone(), &Options::default()).is_ok());
+
+    let desc = "sprites: { s: { rect: [9, 0, 8, 8] } }";
+    let err = compile_sheet(desc, bmp, &Options::default()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite s rect (9, 0, 8, 8) extends past the edge of the 16x8 image"
+    );
+}
+
+#[test]
+fn priority_assigns_colors_first() {
+    let red = sheet(8, 8, |_, _| RGBA::new(255, 0, 0, 255));
+    let green = sheet(8, 8, |_, _| RGBA::new(0, 255, 0, 255));
+    let compile = |priority: i32| {
+        let mut builder = SpritesBuilder::default();
+        let a = "sprites: { a: { rect: [0, 0, 8, 8] } }";
+        let b = format!(
+            "priority: {}\nsprites: {{ b: {{ rect: [0, 0, 8, 8] }} }}",
+            priority
+        );
+        builder
+            .add(
+                serde_yaml::from_str(a).unwrap(),
+                red.clone(),
+                &Options::default(),
+            )
+            .unwrap();
+        builder
+            .add(
+                serde_yaml::from_str(&b).unwrap(),
+                green.clone(),
+                &Options::default(),
+            )
+            .unwrap();
+        builder.compile(&Options::default()).unwrap()
+    };
+
+    let compiled = compile(0);
+    assert_eq!(compiled.sprites["a"].tiles[0][0], 1);
+    assert_eq!(compiled.sprites["b"].tiles[0][0], 2);
+
+    let compiled = compile(1);
+    assert_eq!(compiled.sprites["a"].tiles[0][0], 2);
+    assert_eq!(compiled.sprites["b"].tiles[0][0], 1);
+}
+
+#[test]
+fn trim_crops_transparent_tiles() {
+    // Two 24x16 frames, opaque only in the middle column of tiles of the top row.
+    let bmp = sheet(48, 16, |x, y| {
+        let opaque = (9..12).contains(&(x % 24)) && y < 8 || x == 35 && y == 2;
+        RGBA::new(255, 0, 0, if opaque { 255 } else { 0 })
+    });
+    let desc = "sprites: { s: { rect: [0, 0, 48, 16], frames: 2, trim: true } }";
+    let compiled = compile_sheet(desc, bmp, &Options::default()).unwrap();
+    let sprite = &compiled.sprites["s"];
+    assert_eq!((sprite.width, sprite.height, sprite.frames), (8, 8, 2));
+    assert_eq!(sprite.offset, Some((8, 0)));
+    assert_eq!(sprite.tiles[1][2 * 8 + 3], 1);
+
+    let mut out = String::new();
+    compiled.write_to(&mut out, &Options::default()).unwrap();
+    assert!(out.contains("pub const S_OFFSET: (u16, u16) = (8, 0);"));
+
+    // Pixels alpha_threshold makes transparent aren't kept.
+    let bmp = sheet(24, 8, |x, _| {
+        RGBA::new(255, 0, 0, if x < 8 { 100 } else { 255 })
+    });
+    let desc = "sprites: { s: { rect: [0, 0, 24, 8], trim: true } }";
+    let compiled = compile_sheet(desc, bmp, &Options::default()).unwrap();
+    let sprite = &compiled.sprites["s"];
+    assert_eq!((sprite.width, sprite.offset), (16, Some((8, 0))));
+}
+
+#[test]
+fn obj_priority() {
+    let bmp = sheet(8, 8, |_, _| RGBA::new(255, 0, 0, 255));
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8], obj_priority: 2 } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &Options::default()).unwrap();
+    let mut out = String::new();
+    compiled.write_to(&mut out, &Options::default()).unwrap();
+    assert!(out.contains("pub const S_PRIORITY: u16 = 2;"));
+
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8], obj_priority: 4 } }";
+    let err = compile_sheet(desc, bmp, &Options::default()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite s has obj_priority 4, but OBJ priorities go from 0 to 3"
+    );
+}
+
+#[test]
+fn report_unused_regions() {
+    // A sprite in the first tile, and two stray blobs of pixels beside it.
+    let bmp = sheet(24, 8, |x, y| {
+        let opaque = x < 8 || (10..12).contains(&x) && y < 2 || x == 20 && y == 5;
+        RGBA::new(255, 0, 0, if opaque { 255 } else { 0 })
+    });
+    let mut builder = SpritesBuilder::default();
+    let desc = serde_yaml::from_str("sprites: { s: { rect: [0, 0, 8, 8] } }").unwrap();
+    let covered = builder.add(desc, bmp.clone(), &Options::default()).unwrap();
+    builder.warn_unused_regions(Path::new("sheet.png"), &bmp, &covered);
+    assert_eq!(
+        builder.warnings,
+        ["sheet.png has 5 opaque pixels (7% of them) outside every sprite's rect, in (10, 0, 2, 2), (20, 5, 1, 1)"]
+    );
+
+    let mut builder = SpritesBuilder::default();
+    builder.warn_unused_regions(Path::new("sheet.png"), &bmp, &[(0, 0, 24, 8)]);
+    assert!(builder.warnings.is_empty());
+}
+
+#[test]
+fn preview() {
+    let bmp = sheet(8, 8, |x, y| match (x, y) {
+        (0, 0) => RGBA::new(255, 0, 0, 255),
+        (0, 1) | (1, 1) => RGBA::new(0, 0, 255, 255),
+        _ => RGBA::new(0, 0, 0, 0),
+    });
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+    let compiled = compile_sheet(desc, bmp, &Options::default()).unwrap();
+    let preview = compiled.preview();
+    let lines = preview.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[0], "s (8x8):");
+    assert_eq!(
+        lines[1],
+        "\x1b[48;2;0;0;255m\x1b[38;2;255;0;0m▀\x1b[0m\x1b[38;2;0;0;255m▄\x1b[0m      "
+    );
+    assert_eq!(lines[2], " ".repeat(8));
+}
+
+#[test]
+fn tile_order() {
+    let bmp = sheet(16, 16, |x, y| {
+        RGBA::new((x / 8 * 100) as u8, (y / 8 * 100) as u8, 0, 255)
+    });
+    let desc = "sprites: { s: { rect: [0, 0, 16, 16], tile_order: [0, 2, 1, 3] } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &Options::default()).unwrap();
+    let first_pixels = compiled.sprites["s"]
+        .tiles
+        .iter()
+        .map(|t| t[0])
+        .collect::<Vec<_>>();
+    assert_eq!(first_pixels, [1, 3, 2, 4]);
+
+    // The sheet draws the sprite as it is, undoing an order that isn't its own inverse.
+    let strip = sheet(32, 8, |x, _| RGBA::new((x / 8 * 64) as u8, 0, 0, 255));
+    let desc = "sprites: { s: { rect: [0, 0, 32, 8], tile_order: [1, 2, 0, 3] } }";
+    let compiled = compile_sheet(desc, strip.clone(), &Options::default()).unwrap();
+    assert_exports(&compiled, &strip, "tile_order");
+
+    for order in ["[0, 1, 2]", "[0, 1, 2, 2]", "[0, 1, 2, 4]"] {
+        let desc = format!(
+            "sprites: {{ s: {{ rect: [0, 0, 16, 16], tile_order: {} }} }}",
+            order
+        );
+        let err = compile_sheet(&desc, bmp.clone(), &Options::default())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Sprite s tile_order {} isn't each of its 4 tiles once",
+                order
+            )
+        );
+    }
+}
+
+#[test]
+fn detect_scale() {
+    let options = Options {
+        detect_scale: true,
+        ..Options::default()
+    };
+    let compiled = compile_fixture("scaled/checker.png", &options).unwrap();
+    let sprite = &compiled.sprites["checker"];
+    assert_eq!((sprite.width, sprite.height), (8, 8));
+    assert_eq!(sprite.tiles[0][..3], [0, 1, 0]);
+    assert_eq!(
+        compiled.warnings,
+        ["tests/fixtures/scaled/checker.png looks like a 2x scaled export, as every 2x2 block of pixels is one color and it's 144 DPI, so it was scaled down to 8x8"]
+    );
+
+    let compiled = compile_fixture("scaled/checker.png", &Options::default()).unwrap();
+    assert_eq!(compiled.sprites["checker"].width, 16);
+
+    // Too small to still be whole tiles once scaled down.
+    let bmp = sheet(8, 8, |_, _| RGBA::new(255, 0, 0, 255));
+    assert_eq!(super::detect_scale(&bmp), None);
+}
+
+#[test]
+fn tile_anim() {
+    let bmp = sheet(48, 8, |x, _| RGBA::new(0, 0, (x / 16 * 100) as u8, 255));
+    let desc = "sprites: { water: { rect: [0, 0, 48, 8], frames: 3, tile_anim: true } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &Options::default()).unwrap();
+    let mut out = String::new();
+    compiled.write_to(&mut out, &Options::default()).unwrap();
+    assert!(out.contains("pub const WATER_ANIM_FRAMES: usize = 3;"));
+    assert!(out.contains("pub const WATER_ANIM_STRIDE: usize = 2;"));
+    assert!(!out.contains("WATER_FRAMES"));
+
+    let desc = "sprites: { water: { rect: [0, 0, 16, 8], tile_anim: true } }";
+    let err = compile_sheet(desc, bmp, &Options::default()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite water has tile_anim, but only one frame to animate"
+    );
+}
+
+#[test]
+fn prefix() {
+    let options = Options {
+        loaders: true,
+        prefix: Some("level1".to_owned()),
+        ..Options::default()
+    };
+    let compiled = compile_fixture("groups", &options).unwrap();
+    let mut out = String::new();
+    compiled.write_to(&mut out, &options).unwrap();
+
+    for name in [
+        "pub const LEVEL1_PALETTE: ",
+        "pub struct Level1SpriteMeta ",
+        "pub const LEVEL1_ALL_SPRITES: ",
+        "pub fn level1_sprite_by_name(",
+        "pub const LEVEL1_CURSOR_META: Level1SpriteMeta = ",
+        "pub unsafe fn level1_load_cursor(",
+        "pub unsafe fn level1_upload_obj_tiles<",
+    ] {
+        assert!(out.contains(name), "{} missing", name);
+    }
+    assert!(!out.contains("pub const PALETTE: "));
+
+    let options = Options {
+        prefix: Some("level-1".to_owned()),
+        ..Options::default()
+    };
+    let err = compile_fixture("groups", &options).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "prefix \"level-1\" must be a Rust identifier, as it starts the emitted names"
+    );
+}
+
+#[test]
+fn color_rounding() {
+    let bmp = sheet(8, 8, |_, _| RGBA::new(255, 100, 6, 255));
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+    let bgr555_with = |color_rounding| {
+        let options = Options {
+            color_rounding,
+            ..Options::default()
+        };
+        let compiled = compile_sheet(desc, bmp.clone(), &options)?;
+        Ok::<_, Box<dyn std::error::Error>>(compiled.palette_entries()[1].bgr555(&options))
+    };
+
+    // 100 is 12.5 in 5 bits and 6 is 0.75.
+    assert_eq!(bgr555_with(ColorRounding::Truncate).unwrap(), 31 | 12 << 5);
+    assert_eq!(
+        bgr555_with(ColorRounding::Round).unwrap(),
+        31 | 13 << 5 | 1 << 10
+    );
+    assert_eq!(
+        bgr555_with(ColorRounding::Strict).unwrap_err().to_string(),
+        "Sprite s has color [255, 100, 6], which isn't exactly a BGR555 color, as color_rounding: strict needs"
+    );
+}
+
+#[test]
+fn share_tiles() {
+    // The left tile of each 16x8 frame stays the same, and the right one changes.
+    let bmp = sheet(48, 8, |x, _| match x % 16 {
+        0..=7 => RGBA::new(255, 0, 0, 255),
+        _ => RGBA::new(0, 0, (x / 16 * 80) as u8, 255),
+    });
+    let desc = "sprites: { s: { rect: [0, 0, 48, 8], frames: 3, share_tiles: true } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &Options::default()).unwrap();
+
+    let sprite = &compiled.sprites["s"];
+    assert_eq!(sprite.tiles.len(), 4);
+    assert_eq!(
+        sprite.frame_tiles,
+        Some(vec![vec![0, 1], vec![0, 2], vec![0, 3]])
+    );
+    assert_eq!(
+        compiled.notes,
+        ["Sprite s shares tiles between its frames, emitting 4 of its 6 tiles (67%)"]
+    );
+    let mut out = String::new();
+    compiled.write_to(&mut out, &Options::default()).unwrap();
+    assert!(out.contains("pub const S_FRAME_TILES: [[u16; 2]; 3] = [[0, 1], [0, 2], [0, 3]];"));
+
+    // A sprite with no whole tiles has none to share.
+    let desc = "sprites: { s: { rect: [0, 0, 16, 4], frames: 2, share_tiles: true } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &Options::default()).unwrap();
+    assert!(compiled.sprites["s"].tiles.is_empty());
+    assert_eq!(compiled.sprites["s"].frame_tiles, None);
+
+    let desc = "sprites: { s: { rect: [0, 0, 16, 8], share_tiles: true } }";
+    let err = compile_sheet(desc, bmp, &Options::default()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite s has share_tiles, but only one frame to share tiles between"
+    );
+}
+
+#[test]
+fn max_image_size() {
+    let options = Options {
+        max_image_size: 16,
+        ..Options::default()
+    };
+    let err = compile_fixture("animation", &options).err().unwrap();
+    assert_eq!(
+        err.to_string().replace('\\', "/"),
+        "tests/fixtures/animation/sheet.png is 32x8, bigger than max_image_size 16, so probably isn't a sprite sheet"
+    );
+}
+
+#[test]
+fn export_sheet() {
+    let compiled = compile_fixture("animation", &Options::default()).unwrap();
+    let (buffer, width, height) = compiled.sheet(&Options::default());
+
+    // The 32x8 strip of frames, under its label.
+    assert_eq!((width, height), (4 + 32 + 4, 4 + 6 + 8 + 4));
+    // The top row of the `b` of `blink`.
+    assert_eq!(buffer[4 * width + 5], RGBA::new(224, 224, 224, 255));
+    assert_eq!(buffer[4 * width + 6], RGBA::new(32, 32, 32, 255));
+
+    let sprite = &compiled.sprites["blink"];
+    for frame in 0..4 {
+        let expected = match compiled.index_color()(sprite, sprite.index_at(frame, 3, 3)) {
+            Some(c) => RGBA::new(c.r & !7, c.g & !7, c.b & !7, 255),
+            None => RGBA::new(56, 56, 56, 255),
+        };
+        assert_eq!(buffer[(10 + 3) * width + 4 + frame * 8 + 3], expected);
+    }
+
+    let path = env::temp_dir().join("gba_spriter_export_sheet.png");
+    compiled.export_sheet(&path, &Options::default()).unwrap();
+    let png = decode32_file(&path).unwrap();
+    assert_eq!((png.width, png.height, png.buffer), (width, height, buffer));
+}
+
+/// Checks the one sprite of `compiled` is drawn on the exported sheet, written to `name` in the
+/// temp dir, just as it is in the opaque `bmp`, down to BGR555.
+fn assert_exports(compiled: &CompiledSprites, bmp: &Bitmap<RGBA>, name: &str) {
+    let path = env::temp_dir().join(format!("gba_spriter_{}.png", name));
+    compiled.export_sheet(&path, &Options::default()).unwrap();
+    let png = decode32_file(&path).unwrap();
+    for y in 0..bmp.height {
+        for x in 0..bmp.width {
+            // Past the gap and label above the sprite.
+            let exported = png.buffer[(10 + y) * png.width + 4 + x];
+            let c = bmp.buffer[y * bmp.width + x];
+            let expected = RGBA::new(c.r & !7, c.g & !7, c.b & !7, 255);
+            assert_eq!(exported, expected, "at ({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn tile_align() {
+    let bmp = sheet(24, 8, |x, _| RGBA::new(x as u8 * 8, 0, 0, 255));
+    let desc = "sprites: { s: { rect: [0, 0, 24, 8] } }";
+    let options = Options {
+        tile_align: NonZeroUsize::new(4),
+        ..Options::default()
+    };
+    let compiled = compile_sheet(desc, bmp, &options).unwrap();
+
+    let tiles = &compiled.sprites["s"].tiles;
+    assert_eq!(tiles.len(), 4);
+    assert!(tiles[3].iter().all(|&i| i == TRANSPARENT_INDEX));
+    let mut out = String::new();
+    compiled.write_to(&mut out, &options).unwrap();
+    assert!(out.contains("pub const S_TILE_COUNT: usize = 4;"));
+}
+
+#[test]
+fn check_only() {
+    let options = Options {
+        check_only: true,
+        ..Options::default()
+    };
+    let out = compile_internal("tests/fixtures/transparency", &options).unwrap();
+    assert!(out
+        .lines()
+        .all(|l| l.starts_with("const _: &[u8] = include_bytes!(")));
+
+    let err = compile_internal("tests/fixtures/palette_overflow", &options).unwrap_err();
+    assert!(err.to_string().contains("palette"));
+}
+
+#[test]
+fn bg_sprites_index_the_backdrop_as_0() {
+    // Black, the default backdrop, on the left and red on the right.
+    let bmp = sheet(8, 8, |x, _| {
+        RGBA::new(if x < 4 { 0 } else { 255 }, 0, 0, 255)
+    });
+    let desc = "sprites: { bg: { rect: [0, 0, 8, 8], kind: bg }, obj: { rect: [0, 0, 8, 8] } }";
+    let compiled = compile_sheet(desc, bmp, &Options::default()).unwrap();
+
+    assert_eq!(
+        compiled.sprites["bg"].tiles[0][..8],
+        [0, 0, 0, 0, 1, 1, 1, 1]
+    );
+    assert_eq!(
+        compiled.sprites["obj"].tiles[0][..8],
+        [2, 2, 2, 2, 1, 1, 1, 1]
+    );
+}
+
+#[test]
+fn expect_tiles() {
+    let bmp = sheet(32, 16, |_, _| RGBA::new(255, 0, 0, 255));
+
+    let desc = "sprites: { s: { rect: [0, 0, 32, 8], frames: 2, expect_tiles: 4 } }";
+    assert!(compile_sheet(desc, bmp.clone(), &Options::default()).is_ok());
+
+    let desc = "sprites: { s: { rect: [0, 0, 16, 16], expect_tiles: 2 } }";
+    let err = compile_sheet(desc, bmp, &Options::default()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite s is 4 tiles, but expect_tiles is 2"
+    );
+}
+
+#[test]
+fn truncated_decode() {
+    let mut bmp = sheet(8, 8, |_, _| RGBA::new(0, 0, 0, 0));
+    assert!(check_decoded(Path::new("a.png"), &bmp).is_ok());
+
+    bmp.buffer.pop();
+    let err = check_decoded(Path::new("a.png"), &bmp).unwrap_err();
+    assert_eq!(err.to_string(), "a.png decoded to 63 pixels, but is 8x8");
+}
+
+fn decompress_header(data: &[u8], kind: u8) -> (usize, &[u8]) {
+    assert_eq!(data[0], kind);
+    let len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) >> 8;
+    (len as usize, &data[4..])
+}
+
+/// Decompresses like the BIOS `LZ77UnCompVram`.
+fn lz77_decompress(data: &[u8]) -> Vec<u8> {
+    let (len, mut data) = decompress_header(data, 0x10);
+    let mut out = Vec::new();
+
+    while out.len() < len {
+        let flags = data[0];
+        data = &data[1..];
+        for bit in (0..8).rev() {
+            if out.len() >= len {
+                break;
+            }
+            if flags & 1 << bit == 0 {
+                out.push(data[0]);
+                data = &data[1..];
+                continue;
+            }
+
+            let run = (data[0] >> 4) as usize + 3;
+            let disp = ((data[0] as usize & 0xF) << 8 | data[1] as usize) + 1;
+            assert!(disp >= 2, "Displacement of 1 is unsafe for VRAM");
+            data = &data[2..];
+            for _ in 0..run {
+                out.push(out[out.len() - disp]);
+            }
+        }
+    }
+
+    out
+}
+
+/// Decompresses like the BIOS `RLUnCompVram`.
+fn rle_decompress(data: &[u8]) -> Vec<u8> {
+    let (len, mut data) = decompress_header(data, 0x30);
+    let mut out = Vec::new();
+
+    while out.len() < len {
+        let flag = data[0];
+        if flag & 0x80 != 0 {
+            let run = (flag & 0x7F) as usize + 3;
+            out.extend(std::iter::repeat_n(data[1], run));
+            data = &data[2..];
+        } else {
+            let run = flag as usize + 1;
+            out.extend_from_slice(&data[1..1 + run]);
+            data = &data[1 + run..];
+        }
+    }
+
+    out
+}
+
+#[test]
+fn compression_round_trips() {
+    let mut seed = 1u32;
+    let noise = (0..1000)
+        .map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as u8 % 4
+        })
+        .collect::<Vec<_>>();
+
+    let inputs = [
+        vec![],
+        vec![7],
+        vec![0; 1000],
+        (0..=255).collect(),
+        [1, 2].repeat(300),
+        noise,
+    ];
+
+    for input in &inputs {
+        let lz77 = Compression::Lz77.compress(input);
+        assert_eq!(lz77.len() % 4, 0);
+        assert_eq!(&lz77_decompress(&lz77), input);
+
+        let rle = Compression::Rle.compress(input);
+        assert_eq!(rle.len() % 4, 0);
+        assert_eq!(&rle_decompress(&rle), input);
+    }
+
+    assert!(Compression::Lz77.compress(&[0; 1000]).len() < 200);
+    assert!(Compression::Rle.compress(&[0; 1000]).len() < 50);
+}
+
+#[test]
+fn compression() {
+    check_golden("compression", &Options::default());
+
+    let compiled = compile_fixture("compression", &Options::default()).unwrap();
+    let big = &compiled.sprites["big"];
+    assert_eq!(
+        lz77_decompress(&big.data(&Options::default())),
+        big.tiles.concat()
+    );
+    let small = &compiled.sprites["small"];
+    assert_eq!(
+        rle_decompress(&small.data(&Options::default())),
+        small.tiles.concat()
+    );
+}
+
+#[test]
+fn palette_fill() {
+    let options = Options {
+        backdrop: 0x7C1F,
+        palette_fill: 0x03E0,
+        ..Options::default()
+    };
+    check_golden("palette_fill", &options);
+
+    // Nothing assigns indices out of order yet, so make a gap by hand.
+    let mut compiled = compile_fixture("palette_fill", &options).unwrap();
+    compiled.palette.insert(RGB::new(255, 255, 255), 3);
+    let mut out = String::new();
+    compiled.write_to(&mut out, &options).unwrap();
+    assert!(out.contains(
+        "    Color::from_rgb(31, 0, 31), // transparent\n    \
+         Color::from_rgb(31, 0, 0),\n    \
+         Color::from_rgb(0, 31, 0), // unused\n    \
+         Color::from_rgb(31, 31, 31),\n"
+    ));
+}
+
+#[test]
+fn groups() {
+    check_golden(
+        "groups",
+        &Options {
+            loaders: true,
+            ..Options::default()
+        },
+    );
+
+    for group in ["hud-icons", "mod"] {
+        let desc = format!("{{ group: {:?}, sprites: {{}} }}", group);
+        let err = check_names(Path::new("hud.yml"), &serde_yaml::from_str(&desc).unwrap());
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            format!(
+                "hud.yml has group {:?}, but groups must be Rust identifiers",
+                group
+            )
+        );
+    }
+}
+
+#[test]
+fn duplicate_sprite_names() {
+    let bmp = sheet(8, 8, |_, _| RGBA::new(0, 0, 0, 0));
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+
+    let mut builder = SpritesBuilder::default();
+    builder
+        .add(
+            serde_yaml::from_str(desc).unwrap(),
+            bmp.clone(),
+            &Options::default(),
+        )
+        .unwrap();
+    let err = builder
+        .add(
+            serde_yaml::from_str(desc).unwrap(),
+            bmp,
+            &Options::default(),
+        )
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Sprite s is defined more than once");
+}
+
+#[test]
+fn composite_parts() {
+    // A red body on the left, and on the right a hat that only covers the top row.
+    let bmp = sheet(16, 8, |x, y| match (x < 8, y) {
+        (true, _) => RGBA::new(255, 0, 0, 255),
+        (false, 0) => RGBA::new(0, 255, 0, 255),
+        _ => RGBA::new(0, 0, 0, 0),
+    });
+    let desc = "
+        sprites:
+          s:
+            parts:
+              - rect: [0, 0, 8, 8]
+              - { rect: [8, 0, 8, 8], at: [0, 0] }
+              - { rect: [0, 0, 8, 8], at: [8, 0] }
+    ";
+    let compiled = compile_sheet(desc, bmp, &Options::default()).unwrap();
+
+    let sprite = &compiled.sprites["s"];
+    assert_eq!((sprite.width, sprite.height), (16, 8));
+    let (green, red) = (1, 2);
+    assert!(sprite.tiles[0][..8].iter().all(|&p| p == green));
+    assert!(sprite.tiles[0][8..].iter().all(|&p| p == red));
+    assert!(sprite.tiles[1].iter().all(|&p| p == red));
+}
+
+#[test]
+fn sprite_needs_rect_or_parts() {
+    let bmp = sheet(8, 8, |_, _| RGBA::new(0, 0, 0, 0));
+    for desc in [
+        "sprites: { s: {} }",
+        "sprites: { s: { rect: [0, 0, 8, 8], parts: [{ rect: [0, 0, 8, 8] }] } }",
+        "sprites: { s: { rect: [0, 0, 8, 8], rect_pct: [0, 0, 1, 1] } }",
+    ] {
+        let err = compile_sheet(desc, bmp.clone(), &Options::default())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Sprite s needs exactly one of rect, rect_pct or parts"
+        );
+    }
+}
+
+#[test]
+fn rect_pct() {
+    let bmp = sheet(32, 16, |x, _| {
+        RGBA::new(if x < 16 { 255 } else { 0 }, 0, 0, 255)
+    });
+    let desc = "sprites: { s: { rect_pct: [0.5, 0, 0.25, 0.5] } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &Options::default()).unwrap();
+    let sprite = &compiled.sprites["s"];
+    assert_eq!((sprite.width, sprite.height), (8, 8));
+    assert!(sprite.tiles[0].iter().all(|&p| p == 1));
+    assert_eq!(compiled.palette.len(), 1);
+
+    let desc = "sprites: { s: { rect_pct: [0, 0, 0.3, 0.5] } }";
+    let err = compile_sheet(desc, bmp, &Options::default()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite s rect_pct (0.0, 0.0, 0.3, 0.5) is [0.0, 0.0, 9.6, 8.0] in the 32x16 image, which isn't a whole number of tiles"
+    );
+}
+
+#[test]
+fn visible_colors_never_use_index_0() {
+    let bmp = sheet(8, 8, |_, _| RGBA::new(255, 0, 0, 255));
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+    let compiled = compile_sheet(desc, bmp, &Options::default()).unwrap();
+    assert!(compiled.sprites["s"].tiles[0].iter().all(|&p| p == 1));
+
+    // An included palette is the one way a color could be given index 0.
+    let mut builder = SpritesBuilder::default();
+    let palette = [(0, [255, 0, 0])].into_iter().collect();
+    let err = builder
+        .include_palette(Path::new("game.palette"), palette)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Palette index 0 is [255, 0, 0] in game.palette, but OBJs always draw index 0 as transparent"
+    );
+}
+
+#[test]
+fn json_descriptors() {
+    check_golden("json", &Options::default());
+}
+
+#[test]
+fn keep_indices() {
+    check_golden("keep_indices", &Options::default());
+
+    let mut builder = SpritesBuilder::default();
+    let path = Path::new("tests/fixtures/json/sheet.png");
+    let mut bmp = decode32_file(path).unwrap();
+    let err = builder.keep_indices(path, &mut bmp).unwrap_err();
+    assert_eq!(
+        err.to_string().replace('\\', "/"),
+        "tests/fixtures/json/sheet.png has keep_indices, but isn't an indexed PNG"
+    );
+
+    // Only pixels stored as index 0 are transparent, even if another index has its color.
+    let path = Path::new("tests/fixtures/keep_indices/backdrop_repeated.png");
+    let mut bmp = decode32_file(path).unwrap();
+    builder.keep_indices(path, &mut bmp).unwrap();
+    let alphas = bmp.buffer[..8].iter().map(|p| p.a).collect::<Vec<_>>();
+    assert_eq!(alphas, [0, 0, 0, 0, 255, 255, 255, 255]);
+    assert_eq!(builder.included_palette[&2].0, [255, 0, 255]);
+
+    let err = compile_internal(
+        "tests/fixtures/keep_indices",
+        &Options {
+            bpp: Bpp::Four,
+            ..Options::default()
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string().replace('\\', "/"),
+        "tests/fixtures/keep_indices/sheet.png has keep_indices, but sprite s is 4bpp, which isn't supported"
+    );
+
+    // That's checked before the PNG's palette is included.
+    let mut builder = SpritesBuilder::default();
+    let options = Options {
+        bpp: Bpp::Four,
+        ..Options::default()
+    };
+    let path = Path::new("tests/fixtures/keep_indices/sheet.yml");
+    add_file(&mut builder, path, &Mapping::new(), &options, String::new()).unwrap_err();
+    assert!(builder.included_palette.is_empty());
+}
+
+#[test]
+fn report_memory() {
+    let bmp = sheet(16, 8, |x, _| match x {
+        0..=7 => RGBA::new(255, 0, 0, 255),
+        _ => RGBA::new(0, 0, 255, 255),
+    });
+    let options = Options {
+        report_memory: true,
+        ..Options::default()
+    };
+    let desc = "sprites: { s: { rect: [0, 0, 16, 8] } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &options).unwrap();
+    assert_eq!(
+        compiled.memory_use.unwrap(),
+        "The output takes 134 bytes of ROM (128 of tiles, 6 of palette), 128 of 32768 bytes of OBJ VRAM, and 6 of 512 bytes of OBJ palette RAM"
+    );
+
+    // Compressed tiles take less ROM, but still all of their VRAM once loaded.
+    let desc = "sprites: { s: { rect: [0, 0, 16, 8], compress: rle } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &options).unwrap();
+    let rom = compiled.sprites["s"].data(&options).len();
+    assert!(rom < 128);
+    assert_eq!(
+        compiled.memory_use.unwrap(),
+        format!("The output takes {} bytes of ROM ({} of tiles, 6 of palette), 128 of 32768 bytes of OBJ VRAM, and 6 of 512 bytes of OBJ palette RAM", rom + 6, rom)
+    );
+
+    // An alias is emitted as its original's constant, so its tiles take no more ROM.
+    let desc = "sprites: { a: { rect: [0, 0, 16, 8] }, b: { rect: [0, 0, 16, 8] } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &options).unwrap();
+    assert_eq!(
+        compiled.memory_use.unwrap(),
+        "The output takes 134 bytes of ROM (128 of tiles, 6 of palette), 256 of 32768 bytes of OBJ VRAM, and 6 of 512 bytes of OBJ palette RAM"
+    );
+
+    // Backgrounds take BG VRAM and palette RAM instead.
+    let desc = "sprites: { s: { rect: [0, 0, 16, 8], kind: bg } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &options).unwrap();
+    assert_eq!(
+        compiled.memory_use.unwrap(),
+        "The output takes 134 bytes of ROM (128 of tiles, 6 of palette), 0 of 32768 bytes of OBJ VRAM and 128 bytes of BG VRAM, and 0 of 512 bytes of OBJ palette RAM and 6 of 512 bytes of BG palette RAM"
+    );
+
+    // With palette_only, there are no tiles to store or load.
+    let options = Options {
+        palette_only: true,
+        ..options
+    };
+    let desc = "sprites: { s: { rect: [0, 0, 16, 8] } }";
+    let compiled = compile_sheet(desc, bmp, &options).unwrap();
+    assert_eq!(
+        compiled.memory_use.unwrap(),
+        "The output takes 6 bytes of ROM (0 of tiles, 6 of palette), 0 of 32768 bytes of OBJ VRAM, and 6 of 512 bytes of OBJ palette RAM"
+    );
+}
+
+#[test]
+fn stitched() {
+    check_golden("stitched", &Options::default());
+
+    let stitch = |parts: &str| {
+        let mut desc: SpritesDesc =
+            serde_yaml::from_str(&format!("sprites: {{ boss: {{ parts: {} }} }}", parts)).unwrap();
+        let other = sheet(16, 16, |_, _| RGBA::new(0, 0, 255, 255));
+        desc.images.0.insert("other.png".into(), other);
+        let sheet = sheet(16, 16, |_, _| RGBA::new(255, 0, 0, 255));
+        let mut builder = SpritesBuilder::default();
+        builder
+            .add(desc, sheet, &Options::default())
+            .err()
+            .map(|e| e.to_string())
+    };
+    assert_eq!(
+        stitch("[{ rect: [0, 0, 8, 8] }, { rect: [0, 0, 8, 8], at: [8, 0], image: other.png }]"),
+        None
+    );
+    assert_eq!(
+        stitch("[{ rect: [0, 0, 8, 8] }, { rect: [0, 0, 8, 8], at: [4, 0], image: other.png }]")
+            .unwrap(),
+        "Sprite boss part 1 is 8x8 at (4, 0), but parts stitched from several images must be placed on whole tiles"
+    );
+    assert_eq!(
+        stitch("[{ rect: [0, 0, 8, 8] }, { rect: [0, 0, 8, 8], at: [8, 8], image: other.png }]")
+            .unwrap(),
+        "Sprite boss is stitched from several images, but no part covers its tile (1, 0)"
+    );
+}
+
+#[test]
+fn csv_descriptors() {
+    check_golden("csv", &Options::default());
+}
+
+#[test]
+fn region_table_errors() {
+    let err = |text| region_table::parse(text, ',').unwrap_err().to_string();
+    assert_eq!(
+        err("name,x,y,w,h\na,0,0,8\n"),
+        "Line 2: expected 5 columns of name, x, y, w, h, but found 4"
+    );
+    assert_eq!(
+        err("a,0,0,8,eight"),
+        "Line 1: h should be a whole number, but is \"eight\""
+    );
+    assert_eq!(
+        err("a,0,0,8,8\na,8,0,8,8"),
+        "Line 2: sprite a is already in the table"
+    );
+
+    let tsv = region_table::parse("a\t8\t0\t16\t8", '\t').unwrap();
+    assert_eq!(
+        tsv,
+        serde_yaml::from_str::<Value>("sprites: { a: { rect: [8, 0, 16, 8] } }").unwrap()
+    );
+}
+
+#[test]
+fn yaml_merge_keys() {
+    check_golden("anchors", &Options::default());
+}
+
+#[test]
+fn transparent_is() {
+    check_golden("transparent_index", &Options::default());
+
+    let mut bmp = sheet(2, 1, |x, _| RGBA::new(255, 0, x as u8 * 255, 255));
+    let path = Path::new("sheet.png");
+    apply_transparency(path, &mut bmp, Transparency::Color([255, 0, 255])).unwrap();
+    assert_eq!(bmp.buffer[0].a, 255);
+    assert_eq!(bmp.buffer[1].a, 0);
+
+    let path = Path::new("tests/fixtures/animation/sheet.png");
+    let err = apply_transparency(path, &mut bmp, Transparency::Index(0)).unwrap_err();
+    assert_eq!(
+        err.to_string().replace('\\', "/"),
+        "tests/fixtures/animation/sheet.png has transparent_is index 0, but isn't an indexed PNG"
+    );
+}
+
+#[test]
+fn global_transparent() {
+    let options = Options {
+        global_transparent: Transparency::Index(0),
+        ..Options::default()
+    };
+    let err = compile_fixture("animation", &options).err().unwrap();
+    assert!(err.to_string().ends_with("isn't an indexed PNG"));
+
+    // A descriptor's own transparent_is wins.
+    let options = Options {
+        global_transparent: Transparency::Color([255, 0, 0]),
+        ..Options::default()
+    };
+    check_golden("transparent_index", &options);
+}
+
+#[test]
+fn font() {
+    check_golden("font", &Options::default());
+
+    let bmp = sheet(16, 8, |_, _| RGBA::new(255, 255, 255, 255));
+    let err = |desc| {
+        compile_sheet(desc, bmp.clone(), &Options::default())
+            .err()
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(
+        err("sprites: { f: { rect: [0, 0, 16, 8], kind: font, charset: abc } }"),
+        "Sprite f has 2 glyphs, but its charset has 3 characters"
+    );
+    assert_eq!(
+        err("sprites: { f: { rect: [0, 0, 16, 8], kind: font, charset: aa } }"),
+        "Sprite f has 'a' in its charset more than once"
+    );
+    assert_eq!(
+        err("sprites: { f: { rect: [0, 0, 16, 8], charset: ab } }"),
+        "Sprite f has a charset, but isn't kind: font"
+    );
+
+    // Consecutive characters are looked up by offset rather than a match.
+    let desc = "sprites: { f: { rect: [0, 0, 16, 8], kind: font, charset: AB } }";
+    let compiled = compile_sheet(desc, bmp, &Options::default()).unwrap();
+    let mut out = String::new();
+    compiled.write_to(&mut out, &Options::default()).unwrap();
+    assert!(out.contains("    F[..2].get((c as u32).checked_sub(65)? as usize)\n}"));
+}
+
+#[test]
+fn key_color_regions() {
+    // Two 8x8 sprites outlined in magenta, 6 pixels apart.
+    let bmp = sheet(26, 10, |x, y| {
+        let x = if x >= 16 { x - 16 } else { x };
+        match (x, y) {
+            (10.., _) => RGBA::new(0, 0, 0, 0),
+            (0 | 9, _) | (_, 0 | 9) => RGBA::new(255, 0, 255, 255),
+            _ => RGBA::new(0, 255, 0, 255),
+        }
+    });
+    let path = Path::new("sheet.png");
+    let regions = |desc| {
+        let mut desc: SpritesDesc = serde_yaml::from_str(desc).unwrap();
+        let mut bmp = bmp.clone();
+        resolve_regions(path, &mut desc, &mut bmp).map(|()| (desc, bmp))
+    };
+
+    let (desc, sheet) = regions(
+        "{ regions: { key: [255, 0, 255], names: [a, b] }, sprites: { b: { trim: true } } }",
+    )
+    .unwrap();
+    assert_eq!(desc.sprites["a"].rect, Some((1, 1, 8, 8)));
+    assert_eq!(desc.sprites["b"].rect, Some((17, 1, 8, 8)));
+    assert!(desc.sprites["b"].trim);
+    assert_eq!(sheet.buffer[0].a, 0);
+
+    let (desc, _) = regions("regions: { key: [255, 0, 255] }").unwrap();
+    assert_eq!(
+        desc.sprites.keys().collect::<Vec<_>>(),
+        ["region_0", "region_1"]
+    );
+
+    let err = regions("regions: { key: [255, 0, 255], names: [a] }").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "sheet.png has 2 regions outlined in [255, 0, 255], but 1 region names"
+    );
+}
+
+#[test]
+fn pinned_colors() {
+    let input = syn::parse_str::<options::Input>(
+        r#""dir", pin = { [0, 255, 0]: 1, [255, 0, 0]: 3, [0, 0, 255]: 6 }"#,
+    )
+    .unwrap();
+    let options = input.options;
+
+    let colors = [[255, 0, 0], [10, 10, 10], [0, 255, 0], [20, 20, 20]];
+    let bmp = sheet(8, 8, |x, _| {
+        let [r, g, b] = colors[x % colors.len()];
+        RGBA::new(r, g, b, 255)
+    });
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+    let compiled = compile_sheet(desc, bmp, &options).unwrap();
+
+    // Unpinned colors fill in around the pins.
+    assert_eq!(compiled.sprites["s"].tiles[0][..4], [3, 2, 1, 4]);
+    let entries = compiled.palette_entries();
+    assert_eq!(entries.len(), 7);
+    assert!(matches!(entries[5], PaletteEntry::Unused));
+    assert!(matches!(entries[6], PaletteEntry::Color(c) if c == RGB::new(0, 0, 255)));
+}
+
+#[test]
+fn locked_indices() {
+    let options = Options {
+        pin: BTreeMap::from([([0, 255, 0], 1)]),
+        lock: vec![2, 3, 6],
+        ..Options::default()
+    };
+    let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255]];
+    let bmp = sheet(8, 8, |x, _| {
+        let [r, g, b] = colors[x % colors.len()];
+        RGBA::new(r, g, b, 255)
+    });
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &options).unwrap();
+
+    // Colors skip the locked indices, which are emitted up to the last one.
+    assert_eq!(compiled.sprites["s"].tiles[0][..3], [4, 1, 5]);
+    let entries = compiled.palette_entries();
+    assert_eq!(entries.len(), 7);
+    assert!(matches!(entries[3], PaletteEntry::Locked));
+    assert!(matches!(entries[6], PaletteEntry::Locked));
+
+    let options = Options {
+        lock: (1..=254).collect(),
+        ..Options::default()
+    };
+    let err = compile_sheet(desc, bmp.clone(), &options).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Too many colors to fit into single palette: 3 colors need an index, but only 1 are free, with 254 locked. The sprites with the most colors no other sprite uses are s (3)"
+    );
+
+    let options = Options {
+        pin: BTreeMap::from([([0, 255, 0], 1)]),
+        lock: vec![1],
+        ..Options::default()
+    };
+    let err = compile_sheet(desc, bmp, &options).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Palette index 1 is locked, but color RGB { r: 0, g: 255, b: 0 } is pinned to it"
+    );
+}
+
+#[test]
+fn invalid_pins() {
+    let cases = [
+        (
+            "{ [0, 0, 0]: 0 }",
+            "Color [0, 0, 0] is pinned to palette index 0, which OBJs always draw as transparent",
+        ),
+        (
+            "{ [0, 0, 0]: 4 }",
+            "Color [0, 0, 0] is pinned to palette index 4, below palette_base 16",
+        ),
+        (
+            "{ [0, 0, 0]: 16, [1, 1, 1]: 16 }",
+            "Colors [0, 0, 0] and [1, 1, 1] are both pinned to palette index 16",
+        ),
+    ];
+
+    for (pin, expected) in cases {
+        let options = Options {
+            palette_base: 16,
+            pin: serde_yaml::from_str(pin).unwrap(),
+            ..Options::default()
+        };
+        let bmp = sheet(8, 8, |_, _| RGBA::new(0, 0, 0, 0));
+        let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+        let err = compile_sheet(desc, bmp, &options).err().unwrap();
+        assert_eq!(err.to_string(), expected);
+    }
+}
+
+#[test]
+fn single_file() {
+    let expected = fs::read_to_string("tests/fixtures/transparency/expected.rs").unwrap();
+    for file in ["sheet.yml", "sheet.png"] {
+        let path = format!("tests/fixtures/transparency/{}", file);
+        let actual = compile_internal(&path, &Options::default()).unwrap();
+        assert_eq!(actual.replace('\\', "/"), expected, "{}", file);
+    }
+
+    // Without a descriptor, the whole image becomes one sprite named after the file.
+    let compiled = compile_fixture("single_file/checker.png", &Options::default()).unwrap();
+    let sprite = &compiled.sprites["checker"];
+    assert_eq!((sprite.width, sprite.height), (8, 8));
+
+    let err = compile_fixture("transparency/expected.rs", &Options::default()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "tests/fixtures/transparency/expected.rs isn't a sprite descriptor or PNG"
+    );
+}
+
+#[test]
+fn four_bpp() {
+    check_golden(
+        "four_bpp",
+        &Options {
+            bpp: options::Bpp::Four,
+            loaders: true,
+            ..Options::default()
+        },
+    );
+}
+
+#[test]
+fn mode3() {
+    check_golden("mode3", &Options::default());
+
+    let bmp = sheet(248, 8, |_, _| RGBA::new(255, 0, 0, 255));
+    let desc = "sprites: { s: { rect: [0, 0, 248, 8], kind: mode3 } }";
+    let err = compile_sheet(desc, bmp, &Options::default()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite s is 248x8, which is bigger than the 240x160 Mode 3 screen"
+    );
+}
+
+#[test]
+fn included_palettes() {
+    check_golden("palettes", &Options::default());
+
+    let mut builder = SpritesBuilder::default();
+    let ui = [(1, [255, 255, 255])].into_iter().collect();
+    builder
+        .include_palette(Path::new("ui.palette"), ui)
+        .unwrap();
+    let hud = [(1, [0, 0, 0])].into_iter().collect();
+    let err = builder
+        .include_palette(Path::new("hud.palette"), hud)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Palette index 1 is [255, 255, 255] in ui.palette, but [0, 0, 0] in hud.palette"
+    );
+
+    let bmp = sheet(8, 8, |_, _| RGBA::new(255, 255, 255, 255));
+    builder
+        .add(
+            serde_yaml::from_str("sprites: { s: { rect: [0, 0, 8, 8], bpp: 4 } }").unwrap(),
+            bmp,
+            &Options::default(),
+        )
+        .unwrap();
+    let err = builder.compile(&Options::default()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "ui.palette is included as a palette, but included palettes aren't supported with bpp 4"
+    );
+}
+
+#[test]
+fn binary_palette_files() {
+    // Black, then the BGR555 colors 0x001f (red) and 0x7c00 (blue).
+    let raw = palette_files::parse_raw(&[0, 0, 0x1f, 0, 0, 0x7c]).unwrap();
+    assert_eq!(
+        raw,
+        [(1, [248, 0, 0]), (2, [0, 0, 248])].into_iter().collect()
+    );
+    let err = palette_files::parse_raw(&[0; 3]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Expected 2 bytes for each of up to 256 colors, but found 3 bytes"
+    );
+
+    // Three colors used, the second of them transparent.
+    let mut act = vec![0; 772];
+    act[3..9].copy_from_slice(&[1, 2, 3, 4, 5, 6]);
+    act[768..].copy_from_slice(&[0, 3, 0, 1]);
+    let colors = palette_files::parse_act(&act).unwrap();
+    assert_eq!(colors, [(2, [4, 5, 6])].into_iter().collect());
+    let err = palette_files::parse_act(&act[..700]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Expected 768 bytes, or 772 with a color count, but found 700"
+    );
+}
+
+#[test]
+fn bank_packing_finds_fewest_banks() {
+    // Placing the biggest sets first takes 3 banks, but 7 + 5 + 3 and 6 + 5 + 4 fill 2.
+    let mut next = 0;
+    let sets = [7, 6, 5, 5, 4, 3]
+        .iter()
+        .map(|&n| {
+            next += n;
+            (next - n..next)
+                .map(|c| RGB::new(c as u8, 0, 0))
+                .collect::<banks::Colors>()
+        })
+        .collect::<Vec<_>>();
+
+    let (banks, assignment, optimal) = banks::pack(&[], &sets);
+    assert!(optimal);
+    assert_eq!(banks.len(), 2);
+    for (set, &bank) in sets.iter().zip(&assignment) {
+        assert!(set.is_subset(&banks[bank]));
+    }
+    assert!(banks.iter().all(|b| b.len() <= banks::BANK_COLORS));
+}
+
+#[test]
+fn mixed_bpp() {
+    check_golden(
+        "mixed_bpp",
+        &Options {
+            bpp: options::Bpp::Four,
+            loaders: true,
+            ..Options::default()
+        },
+    );
+}
+
+#[test]
+fn mixed_bpp_pin() {
+    let options = Options {
+        pin: BTreeMap::from([([0, 255, 0], 20)]),
+        ..Options::default()
+    };
+    let bmp = sheet(16, 8, |x, _| match x / 8 {
+        0 => RGBA::new(0, 255, 0, 255),
+        _ => RGBA::new(255, 0, 0, 255),
+    });
+    let desc = "sprites: { a: { rect: [0, 0, 8, 8] }, b: { rect: [8, 0, 8, 8], bpp: 4 } }";
+    let compiled = compile_sheet(desc, bmp, &options).unwrap();
+
+    // The pin holds for the 8bpp sprite, and banks start past it.
+    assert_eq!(compiled.sprites["a"].tiles[0][0], 20);
+    assert_eq!(compiled.first_bank, 2);
+    assert_eq!(compiled.sprites["b"].bank, Some(2));
+}
+
+#[test]
+fn four_bpp_bank_limits() {
+    let four_bpp = |palette_base| Options {
+        bpp: options::Bpp::Four,
+        palette_base,
+        ..Options::default()
+    };
+    // Every tile has 15 colors of its own, so needs a bank to itself.
+    let bmp = sheet(8 * 16, 8, |x, y| {
+        RGBA::new((x / 8) as u8, ((y * 8 + x % 8) % 15) as u8, 0, 255)
+    });
+    let desc = |n: usize| {
+        let sprites = (0..n)
+            .map(|i| format!("s{}: {{ rect: [{}, 0, 8, 8] }}", i, i * 8))
+            .collect::<Vec<_>>();
+        format!("sprites: {{ {} }}", sprites.join(", "))
+    };
+
+    let compiled = compile_sheet(&desc(16), bmp.clone(), &four_bpp(0)).unwrap();
+    assert_eq!(compiled.banks.len(), 16);
+    assert!(compiled
+        .notes
+        .contains(&"4bpp sprites use 16 palette banks".to_string()));
+    let compiled = compile_sheet(&desc(1), bmp.clone(), &four_bpp(0)).unwrap();
+    assert!(compiled
+        .notes
+        .contains(&"4bpp sprites use 1 palette bank".to_string()));
+
+    let err = compile_sheet(&desc(16), bmp.clone(), &four_bpp(16))
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "4bpp sprites need 16 palette banks, but only 15 are free from bank 1"
+    );
+
+    let err = compile_sheet(&desc(1), bmp.clone(), &four_bpp(8))
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "palette_base 8 must be a multiple of 16 with bpp 4"
+    );
+
+    let desc = "sprites: { s: { rect: [0, 0, 16, 8] } }";
+    let err = compile_sheet(desc, bmp, &four_bpp(0)).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite s has 30 colors, but a 4bpp palette bank holds at most 15"
+    );
+}
+
+#[test]
+fn four_bpp_indices_fit_in_a_nibble() {
+    let mut sprite = CompiledSprite {
+        width: 8,
+        height: 8,
+        frames: 1,
+        durations: Vec::new(),
+        compression: None,
+        group: None,
+        bank: Some(2),
+        tile_xy: None,
+        offset: None,
+        obj_priority: None,
+        z: None,
+        tags: BTreeSet::new(),
+        tile_anim: false,
+        frame_tiles: None,
+        tile_map: vec![0],
+        tile_sources: Vec::new(),
+        sheet_frames: None,
+        charset: None,
+        tiles: vec![vec![15; 64]],
+    };
+    assert!(sprite.check_indices("s").is_ok());
+
+    sprite.tiles[0][10] = 16;
+    assert_eq!(
+        sprite.check_indices("s").unwrap_err().to_string(),
+        "Sprite s uses palette index 16 of bank 2, but 4bpp tiles can only index 0 to 15"
+    );
+
+    // 8bpp tiles can use the whole palette.
+    sprite.bank = None;
+    assert!(sprite.check_indices("s").is_ok());
+}
+
+#[test]
+fn unknown_fields() {
+    let desc: SpritesDesc = serde_yaml::from_str(
+        "
+        editor: { zoom: 4 }
+        anchors: { a: [8, 0] }
+        sprites:
+          s:
+            rect: [0, 0, 8, 8]
+            anchor: a
+            recolor: { [1, 2, 3]: [4, 5, 6] }
+            hint: walk cycle
+          t:
+            parts: [{ rect: [0, 0, 8, 8], layer: 2 }]
+        ",
+    )
+    .unwrap();
+    assert_eq!(desc.anchors["a"], (8, 0));
+    assert_eq!(desc.sprites["s"].recolor[&[1, 2, 3]], [4, 5, 6]);
+
+    let path = Path::new("a.yml");
+    let mut builder = SpritesBuilder::default();
+    let err = builder
+        .check_unknown_fields(path, &desc, &Options::default())
+        .unwrap_err();
+    assert_eq!(err.to_string(), "a.yml has unknown field `editor`");
+
+    let options = Options {
+        allow_unknown_fields: true,
+        ..Options::default()
+    };
+    builder.check_unknown_fields(path, &desc, &options).unwrap();
+    assert_eq!(
+        builder.warnings,
+        [
+            "a.yml has unknown field `editor`, which is ignored",
+            "a.yml has unknown field `hint` in sprite s, which is ignored",
+            "a.yml has unknown field `layer` in a part of sprite t, which is ignored",
+        ]
+    );
+}
+
+#[test]
+fn alpha_threshold() {
+    let bmp = sheet(8, 8, |x, _| RGBA::new(255, 0, 0, [0, 127, 128, 255][x % 4]));
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+
+    let compiled = compile_sheet(desc, bmp.clone(), &Options::default()).unwrap();
+    assert_eq!(compiled.sprites["s"].tiles[0][..4], [0, 0, 1, 1]);
+    assert_eq!(
+        compiled.warnings,
+        ["Sprite s has 32 semi-transparent pixels, made opaque or transparent by alpha_threshold 128"]
+    );
+
+    let options = Options {
+        alpha_threshold: 0,
+        ..Options::default()
+    };
+    let compiled = compile_sheet(desc, bmp.clone(), &options).unwrap();
+    assert_eq!(compiled.sprites["s"].tiles[0][..4], [0, 1, 1, 1]);
+
+    // Mode 3 bitmaps draw the backdrop where the threshold makes them transparent.
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8], kind: mode3 } }";
+    let options = Options {
+        backdrop: 0x1234,
+        ..Options::default()
+    };
+    let compiled = compile_sheet(desc, bmp, &options).unwrap();
+    assert_eq!(
+        compiled.bitmaps["s"].pixels[..4],
+        [0x1234, 0x1234, 0x001f, 0x001f]
+    );
+    assert_eq!(compiled.warnings.len(), 1);
+}
+
+#[test]
+fn solid_sprites() {
+    check_golden("solid", &Options::default());
+
+    let compiled = compile_fixture("solid", &Options::default()).unwrap();
+    assert_eq!(
+        compiled.notes,
+        [
+            "Sprite empty is entirely transparent",
+            "Sprite fill is a single solid color"
+        ]
+    );
+}
+
+#[test]
+fn nibble_order() {
+    let mut tiles = vec![vec![0; 64]];
+    tiles[0][..8].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    let sprite = CompiledSprite {
+        width: 8,
+        height: 8,
+        frames: 1,
+        durations: Vec::new(),
+        compression: None,
+        group: None,
+        bank: Some(0),
+        tile_xy: None,
+        offset: None,
+        obj_priority: None,
+        z: None,
+        tags: BTreeSet::new(),
+        tile_anim: false,
+        frame_tiles: None,
+        tile_map: vec![0],
+        tile_sources: Vec::new(),
+        sheet_frames: None,
+        charset: None,
+        tiles,
+    };
+
+    let low_first = sprite.tile_bytes(&Options::default());
+    assert_eq!(low_first[0][..4], [0x21, 0x43, 0x65, 0x87]);
+
+    let options = Options {
+        nibble_order: options::NibbleOrder::HighFirst,
+        ..Options::default()
+    };
+    let high_first = sprite.tile_bytes(&options);
+    assert_eq!(high_first[0][..4], [0x12, 0x34, 0x56, 0x78]);
+}
+
+#[test]
+fn little_endian_output() {
+    // The GBA is little-endian whatever the host is, so the first byte of each word is lowest.
+    let options = Options {
+        hex_tiles: true,
+        ..Options::default()
+    };
+    let tile = (1..=32).collect::<Vec<u8>>();
+    assert!(tile_literal(&tile, &options).starts_with("[0x04030201, 0x08070605, "));
+
+    // A 0x123 byte RLE header is the kind, then the length lowest byte first.
+    let data = Compression::Rle.compress(&[0; 0x123]);
+    assert_eq!(data[..4], [0x30, 0x23, 0x01, 0x00]);
+}
+
+#[test]
+fn atlas() {
+    let bmp = sheet(80, 64, |x, y| {
+        RGBA::new(1, 0, 0, if x < 64 || y < 32 { 255 } else { 0 })
+    });
+    let desc = "
+        sprites:
+          big: { rect: [0, 0, 64, 64] }
+          tall: { rect: [64, 0, 8, 32] }
+          small: { rect: [72, 0, 8, 8] }
+    ";
+    let options = Options {
+        atlas: true,
+        bpp: options::Bpp::Four,
+        ..Options::default()
+    };
+    let compiled = compile_sheet(desc, bmp.clone(), &options).unwrap();
+    let xy = |id: &str| compiled.sprites[id].tile_xy.unwrap();
+    assert_eq!(
+        (xy("big"), xy("tall"), xy("small")),
+        ((0, 0), (8, 0), (9, 0))
+    );
+    let mut out = String::new();
+    compiled.write_to(&mut out, &options).unwrap();
+    assert!(out.contains("pub const TALL_TILE_XY: (u8, u8) = (8, 0);"));
+
+    // 8bpp tiles take two slots, so seven 64x64 sprites leave a 16x8 gap, too narrow for
+    // a sprite 10 tiles wide.
+    let mut sprites = (0..7)
+        .map(|i| format!("s{}: {{ rect: [0, 0, 64, 64] }}", i))
+        .collect::<Vec<_>>();
+    sprites.push("wide: { rect: [0, 0, 80, 8] }".to_string());
+    let desc = format!(
+        "{{ allow_overlaps: true, sprites: {{ {} }} }}",
+        sprites.join(", ")
+    );
+    let options = Options {
+        atlas: true,
+        ..Options::default()
+    };
+    let err = compile_sheet(&desc, bmp, &options).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite wide needs a 20x1 block of tile slots, which doesn't fit in the 32x32 OBJ tile \
+         space with the other sprites"
+    );
+}
+
+#[test]
+fn atlas_rows_dont_wrap() {
+    let bmp = sheet(160, 8, |_, y| RGBA::new(255, y as u8, 0, 255));
+    let options = Options {
+        atlas: true,
+        ..Options::default()
+    };
+    // 5 frames of 8bpp tiles 4 across take 40 slots in a row.
+    let desc = "sprites: { run: { rect: [0, 0, 160, 8], frames: 5 } }";
+    let err = compile_sheet(desc, bmp.clone(), &options).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite run is 40 tile slots wide, counting 8bpp tiles as two and its 5 frames side by \
+         side, so it would wrap the 32 slot rows of 2D mapped OBJ VRAM"
+    );
+
+    let four_bpp = Options {
+        bpp: options::Bpp::Four,
+        ..options
+    };
+    assert!(compile_sheet(desc, bmp, &four_bpp).is_ok());
+}
+
+#[test]
+fn obj_vram_limit() {
+    let bmp = sheet(64, 64, |_, _| RGBA::new(255, 0, 0, 255));
+    let desc = |n, extra: &str| {
+        let mut sprites = (0..n)
+            .map(|i| format!("s{}: {{ rect: [0, 0, 64, 64] }}", i))
+            .collect::<Vec<_>>();
+        sprites.push(extra.to_string());
+        format!(
+            "{{ allow_overlaps: true, sprites: {{ {} }} }}",
+            sprites.join(", ")
+        )
+    };
+
+    // 8bpp tiles take two slots, so only 8 64x64 sprites fit.
+    let err = compile_sheet(&desc(9, ""), bmp.clone(), &Options::default())
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "The sprites need 1152 tile slots of OBJ VRAM, counting 8bpp tiles as two, but it only \
+         has 1024. The biggest are s0 (128), s1 (128), s2 (128), s3 (128), s4 (128)"
+    );
+
+    // Backgrounds don't go in OBJ VRAM, but Mode 3 bitmaps take half of it.
+    let bg = "bg: { rect: [0, 0, 64, 64], kind: bg }";
+    assert!(compile_sheet(&desc(8, bg), bmp.clone(), &Options::default()).is_ok());
+    let mode3 = "screen: { rect: [0, 0, 8, 8], kind: mode3 }";
+    let err = compile_sheet(&desc(5, mode3), bmp, &Options::default())
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .contains("need 640 tile slots of OBJ VRAM, counting 8bpp tiles as two, but it only has 512 with Mode 3 bitmaps."));
+}
+
+#[test]
+fn include_base_from_call_site() {
+    let cwd = Path::new("/ws");
+    // A single crate, where the working directory is the crate root.
+    assert_eq!(include_base(Path::new("src/lib.rs"), cwd), "../");
+    // A workspace member, where it's the workspace root.
+    assert_eq!(
+        include_base(Path::new("crates/game/src/sprites/mod.rs"), cwd),
+        "../../../../"
+    );
+    assert_eq!(include_base(Path::new("build.rs"), cwd), "");
+    assert_eq!(include_base(Path::new("/elsewhere/lib.rs"), cwd), "/ws/");
+    assert_eq!(include_base(Path::new("../game/lib.rs"), cwd), "/ws/");
+
+    let options = Options {
+        include_base: Some("/ws/".to_owned()),
+        ..Options::default()
+    };
+    let out = compile_internal("tests/fixtures/transparency", &options).unwrap();
+    assert!(out.replace('\\', "/").starts_with(
+        "const _: &[u8] = include_bytes!(\"/ws/tests/fixtures/transparency/sheet.yml\");"
+    ));
+}
+
+#[test]
+fn shared_image() {
+    check_golden("shared_image", &Options::default());
+}
+
+#[test]
+fn extract_rect_matches_pixel_by_pixel() {
+    let bmp = sheet(37, 23, |x, y| RGBA::new(x as u8, y as u8, 0, 255));
+
+    for rect in [
+        (0, 0, 37, 23),
+        (5, 3, 8, 16),
+        (36, 22, 1, 1),
+        (10, 10, 0, 4),
+    ] {
+        let (base_x, base_y, w, h) = rect;
+        let mut expected = Vec::new();
+        for y in base_y..base_y + h {
+            for x in base_x..base_x + w {
+                expected.push(bmp.buffer[y * bmp.width + x]);
+            }
+        }
+
+        let actual = extract_rect(&bmp, rect);
+        assert_eq!(actual.buffer, expected, "{:?}", rect);
+        assert_eq!((actual.width, actual.height), (w, h));
+    }
+}
+
+#[test]
+fn shared_palette() {
+    let path = env::temp_dir().join(format!("spriter-shared-{}.yml", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let options = Options {
+        shared_palette: Some(path.display().to_string()),
+        ..Options::default()
+    };
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+    let compile = |colors: [[u8; 3]; 2]| {
+        let bmp = sheet(8, 8, |x, _| {
+            let [r, g, b] = colors[x % 2];
+            RGBA::new(r, g, b, 255)
+        });
+        let compiled = compile_sheet(desc, bmp, &options).unwrap();
+        compiled.write_shared_palette(&path).unwrap();
+        compiled.sprites["s"].tiles[0][..2].to_vec()
+    };
+
+    assert_eq!(compile([[255, 0, 0], [0, 255, 0]]), [1, 2]);
+    // Green keeps its index, and blue goes after the colors already there.
+    assert_eq!(compile([[0, 0, 255], [0, 255, 0]]), [3, 2]);
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "---\n1:\n  - 255\n  - 0\n  - 0\n2:\n  - 0\n  - 255\n  - 0\n3:\n  - 0\n  - 0\n  - 255\n"
+    );
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn nineslice() {
+    // A 24x16 panel, each tile a different color.
+    let bmp = sheet(24, 16, |x, y| {
+        RGBA::new(x as u8 / 8 * 80, y as u8 / 8 * 80, 255, 255)
+    });
+    let desc =
+        "sprites: { panel: { rect: [0, 0, 24, 16], kind: nineslice, insets: [8, 8, 8, 0] } }";
+    let err = compile_sheet(desc, bmp.clone(), &Options::default())
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite panel is 24x16, which insets [8, 8, 8, 0] don't leave all nine slices of"
+    );
+
+    let bmp = sheet(24, 24, |x, y| {
+        RGBA::new(x as u8 / 8 * 80, y as u8 / 8 * 80, 255, 255)
+    });
+    let err = |desc| {
+        compile_sheet(desc, bmp.clone(), &Options::default())
+            .err()
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(
+        err("sprites: { panel: { rect: [0, 0, 24, 24], kind: nineslice, insets: [4, 8, 8, 8] } }"),
+        "Sprite panel is 24x24 with insets [4, 8, 8, 8], which don't cut it into whole 8x8 tiles"
+    );
+    assert_eq!(
+        err("sprites: { panel: { rect: [0, 0, 24, 24], kind: nineslice } }"),
+        "Sprite panel has kind: nineslice, but no insets"
+    );
+    assert_eq!(
+        err("sprites: { panel: { rect: [0, 0, 24, 24], insets: [8, 8, 8, 8] } }"),
+        "Sprite panel has insets, but isn't kind: nineslice"
+    );
+
+    let desc =
+        "sprites: { panel: { rect: [0, 0, 24, 24], kind: nineslice, insets: [8, 8, 8, 8] } }";
+    let compiled = compile_sheet(desc, bmp, &Options::default()).unwrap();
+    assert_eq!(compiled.sprites.len(), 9);
+    let top_right = &compiled.sprites["panel_top_right"];
+    let center = &compiled.sprites["panel_center"];
+    assert_eq!((top_right.width, top_right.height), (8, 8));
+    assert_ne!(top_right.tiles, center.tiles);
+
+    let mut out = String::new();
+    compiled.write_to(&mut out, &Options::default()).unwrap();
+    assert!(out.contains("pub const PANEL_INSETS: (u16, u16, u16, u16) = (8, 8, 8, 8);"));
+    assert!(out.contains("pub const PANEL_BOTTOM_LEFT: "));
+}
+
+#[test]
+fn emit_tests() {
+    let bmp = sheet(16, 8, |x, _| RGBA::new(255, x as u8 * 16, 0, 255));
+    let desc = "sprites: { a: { rect: [0, 0, 8, 8] }, b: { rect: [8, 0, 8, 8], bpp: 4 } }";
+    let options = Options {
+        emit_tests: true,
+        ..Options::default()
+    };
+    let compiled = compile_sheet(desc, bmp, &options).unwrap();
+    let mut out = String::new();
+    compiled.write_to(&mut out, &options).unwrap();
+
+    let tests = &out[out.find("#[cfg(test)]\nmod spriter_tests {").unwrap()..];
+    assert!(tests.contains("assert!(PALETTE_BASE + PALETTE.len() <= 256);"));
+    assert!(tests.contains("fn a_tiles() {\n        assert_eq!(A.len(), 1);"));
+    // 4bpp indices are looked up in the sprite's bank.
+    assert!(tests.contains(".flat_map(|b| [b & 0xf, b >> 4])"));
+    assert!(tests.contains("colors.contains(&(16 + i as usize))"));
+
+    // Ids differing only by case are fine in different modules, and their tests are too, as
+    // are ids that look like another's group and id together.
+    let bmp = sheet(8, 8, |_, _| RGBA::new(255, 0, 0, 255));
+    let mut builder = SpritesBuilder::default();
+    for desc in [
+        "{ group: x, sprites: { A: { rect: [0, 0, 8, 8] } } }",
+        "{ group: y, sprites: { a: { rect: [0, 0, 8, 8] } } }",
+        "{ sprites: { x_a: { rect: [0, 0, 8, 8] } } }",
+    ] {
+        builder
+            .add(serde_yaml::from_str(desc).unwrap(), bmp.clone(), &options)
+            .unwrap();
+    }
+    let compiled = builder.compile(&options).unwrap();
+    let mut out = String::new();
+    compiled.write_to(&mut out, &options).unwrap();
+    let tests = &out[out.find("mod spriter_tests").unwrap()..];
+    assert!(tests.contains("    fn x_a_tiles() {\n        assert_eq!(X_A.len(), 1);"));
+    assert!(tests.contains(
+        "    mod x {\n        use super::super::*;\n\n        #[test]\n        fn a_tiles() {\n            assert_eq!(x::A.len(), 1);"
+    ));
+    assert!(tests.contains("        fn a_tiles() {\n            assert_eq!(y::A.len(), 1);"));
+
+    let desc = "sprites: { A: { rect: [0, 0, 8, 8] }, a: { rect: [0, 0, 8, 8] } }";
+    let err = compile_sheet(desc, bmp, &options).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprites A and a would both be emitted as A"
+    );
+}
+
+#[test]
+fn dir_defaults() {
+    check_golden("defaults", &Options::default());
+
+    let compiled = compile_fixture("defaults", &Options::default()).unwrap();
+    assert_eq!(compiled.sprites["panel"].bank, Some(1));
+    assert_eq!(compiled.sprites["panel"].group, None);
+    let gem = &compiled.sprites["gem"];
+    assert_eq!((gem.bank, gem.obj_priority), (None, Some(1)));
+    assert_eq!(gem.group.as_deref(), Some("icons"));
+
+    let path = Path::new("tests/fixtures/config/spriter.yml");
+    let err = read_defaults(path).unwrap_err();
+    assert!(err.to_string().ends_with(
+        "but defaults can only set transparent_is, group, priority, allow_overlaps and sprite"
+    ));
+}
+
+#[test]
+fn aligned_palette() {
+    let bmp = sheet(8, 8, |x, _| RGBA::new(255, 0, x as u8 / 4 * 255, 255));
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+    let options = Options {
+        aligned_palette: true,
+        ..Options::default()
+    };
+    let compiled = compile_sheet(desc, bmp, &options).unwrap();
+    let mut out = String::new();
+    compiled.write_to(&mut out, &options).unwrap();
+    assert!(out.contains(
+        "#[repr(align(4))]\npub struct Palette(pub [u16; 3]);\n\
+         pub const PALETTE_ALIGNED: Palette = Palette([\n    0x0000, 0x001f, 0x7c1f,\n]);"
+    ));
+}
+
+#[test]
+fn duplicate_sprites_are_aliased() {
+    let bmp = sheet(16, 8, |x, y| RGBA::new(255, (x % 8 + y) as u8 * 16, 0, 255));
+    let desc = "sprites: { a: { rect: [0, 0, 8, 8] }, b: { rect: [8, 0, 8, 8] }, \
+                c: { rect: [8, 0, 8, 8], compress: lz77 } }";
+    let compiled = compile_sheet(desc, bmp, &Options::default()).unwrap();
+    assert_eq!(compiled.aliases(), BTreeMap::from([("b", "a")]));
+    assert!(compiled
+        .notes
+        .contains(&"Sprite b has the same tiles as a, so it's emitted as an alias of them".into()));
+
+    let mut out = String::new();
+    compiled.write_to(&mut out, &Options::default()).unwrap();
+    assert!(out.contains("/// The same tiles as `A`.\npub const B: &'static [[u8; 64]] = A;\n"));
+}
+
+#[test]
+fn tile_size() {
+    // Each 8x8 tile a different color, numbered row by row.
+    let colors = (0..8u8)
+        .map(|i| RGBA::new(i * 32, 0, 255, 255))
+        .collect::<Vec<_>>();
+    let bmp = sheet(32, 16, |x, y| colors[y / 8 * 4 + x / 8]);
+    let desc = "sprites: { s: { rect: [0, 0, 32, 16] } }";
+    let options = Options {
+        tile_size: 16,
+        ..Options::default()
+    };
+    let compiled = compile_sheet(desc, bmp.clone(), &options).unwrap();
+    // The sheet still draws the sprite as it is, whatever order its tiles are stored in.
+    assert_exports(&compiled, &bmp, "tile_size");
+    let color_of = compiled
+        .palette
+        .iter()
+        .map(|(c, &i)| (i, c.r / 32))
+        .collect::<HashMap<_, _>>();
+    let order = compiled.sprites["s"]
+        .tiles
+        .iter()
+        .map(|tile| color_of[&tile[0]])
+        .collect::<Vec<_>>();
+    // The tiles of each 16x16 block come together.
+    assert_eq!(order, [0, 1, 4, 5, 2, 3, 6, 7]);
+
+    let err = |desc, tile_size| {
+        let options = Options {
+            tile_size,
+            ..Options::default()
+        };
+        compile_sheet(desc, bmp.clone(), &options)
+            .err()
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(
+        err("sprites: { s: { rect: [0, 0, 24, 16] } }", 16),
+        "Sprite s is 24x16, which isn't whole 16x16 blocks of tile_size 16"
+    );
+    assert_eq!(
+        err(desc, 12),
+        "tile_size 12 must be a multiple of the GBA's 8 pixel tiles"
+    );
+}
+
+#[test]
+fn sprite_data() {
+    let bmp = sheet(16, 8, |x, _| RGBA::new(255, x as u8 * 16, 0, 255));
+    let desc = "sprites: { a: { rect: [0, 0, 8, 8] }, b: { rect: [8, 0, 8, 8], compress: lz77 } }";
+    let options = Options {
+        sprite_data: true,
+        ..Options::default()
+    };
+    let compiled = compile_sheet(desc, bmp, &options).unwrap();
+    let mut out = String::new();
+    compiled.write_to(&mut out, &options).unwrap();
+    assert!(out.contains("pub struct SpriteData<T: 'static> {"));
+    assert!(out.contains(
+        "pub const A_DATA: SpriteData<[u8; 64]> = SpriteData { palette: &PALETTE, \
+         palette_base: PALETTE_BASE, tiles: A, width: 8, height: 8 };"
+    ));
+    assert!(!out.contains("B_DATA"));
+}
+
+#[test]
+fn fixed_palbank() {
+    // a and b have 4 colors each, and c 12 of its own.
+    let bmp = sheet(24, 8, |x, y| match x / 8 {
+        0 => RGBA::new(x as u8 % 4, 0, 0, 255),
+        1 => RGBA::new(x as u8 % 4, 1, 0, 255),
+        _ => RGBA::new(((y * 8 + x % 8) % 12) as u8, 2, 0, 255),
+    });
+    let four_bpp = Options {
+        bpp: options::Bpp::Four,
+        ..Options::default()
+    };
+    let desc = "sprites: { a: { rect: [0, 0, 8, 8], palbank: 3 }, b: { rect: [8, 0, 8, 8] }, \
+                c: { rect: [16, 0, 8, 8] } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &four_bpp).unwrap();
+    let bank = |id: &str| compiled.sprites[id].bank;
+    // b fits in a's bank, and c takes the first free one.
+    assert_eq!(
+        (bank("a"), bank("b"), bank("c")),
+        (Some(3), Some(3), Some(0))
+    );
+    assert_eq!(compiled.banks.len(), 4);
+    assert!(compiled.banks[1].is_empty() && compiled.banks[2].is_empty());
+
+    let desc = "sprites: { a: { rect: [0, 0, 8, 8], palbank: 3 }, \
+                c: { rect: [16, 0, 8, 8], palbank: 3 } }";
+    let err = compile_sheet(desc, bmp.clone(), &four_bpp).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprites a, c have palbank 3, but need 16 colors between them, and a bank holds at most 15"
+    );
+
+    let desc = "sprites: { a: { rect: [0, 0, 8, 8], palbank: 16 } }";
+    let err = compile_sheet(desc, bmp.clone(), &four_bpp).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite a has palbank 16, but only banks 0 to 15 are free"
+    );
+
+    let err = compile_sheet(desc, bmp, &Options::default()).err().unwrap();
+    assert_eq!(err.to_string(), "Sprite a has a palbank, but isn't 4bpp");
+}
+
+#[test]
+fn max_colors() {
+    // 8 colors, one per column.
+    let bmp = sheet(8, 8, |x, _| RGBA::new(x as u8 * 32, 0, 0, 255));
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+    let capped = |max_colors, palette_base| Options {
+        max_colors: Some(max_colors),
+        palette_base,
+        ..Options::default()
+    };
+    // Index 0 counts against the cap.
+    assert!(compile_sheet(desc, bmp.clone(), &capped(9, 0)).is_ok());
+    let err = compile_sheet(desc, bmp.clone(), &capped(8, 0))
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Too many colors to fit into single palette: 8 colors need an index, but only 7 are \
+         free, with max_colors 8. The sprites with the most colors no other sprite uses are s (8)"
+    );
+    assert!(compile_sheet(desc, bmp.clone(), &capped(8, 16)).is_ok());
+
+    let err = compile_sheet(desc, bmp.clone(), &capped(250, 16))
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "max_colors 250 must be from 1 to 240, the palette entries from palette_base 16"
+    );
+
+    // The first bank is the only one wholly within 20 colors.
+    let four_bpp = Options {
+        bpp: options::Bpp::Four,
+        ..capped(20, 0)
+    };
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8], palbank: 1 } }";
+    let err = compile_sheet(desc, bmp, &four_bpp).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite s has palbank 1, but only banks 0 to 0 are free"
+    );
+}
+
+#[test]
+fn z_order() {
+    let bmp = sheet(16, 8, |x, _| RGBA::new(255, x as u8 * 16, 0, 255));
+    let desc = "sprites: { back: { rect: [0, 0, 8, 8] }, front: { rect: [8, 0, 8, 8], z: 2 } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &Options::default()).unwrap();
+    let mut out = String::new();
+    compiled.write_to(&mut out, &Options::default()).unwrap();
+    assert!(out.contains("pub const BACK_Z: u8 = 0;"));
+    assert!(out.contains("pub const FRONT_Z: u8 = 2;"));
+
+    // Without any, there's nothing to stack.
+    let desc = "sprites: { back: { rect: [0, 0, 8, 8] } }";
+    let compiled = compile_sheet(desc, bmp, &Options::default()).unwrap();
+    let mut out = String::new();
+    compiled.write_to(&mut out, &Options::default()).unwrap();
+    assert!(!out.contains("_Z:"));
+}
+
+#[test]
+fn tiles_round_trip() {
+    // 14 colors and transparent corners, so it fits a 4bpp bank too.
+    let bmp = sheet(16, 16, |x, y| match (x + y) % 15 {
+        0 => RGBA::new(0, 0, 0, 0),
+        n => RGBA::new(n as u8 * 16, 255 - n as u8 * 16, n as u8 % 3 * 100, 255),
+    });
+    let desc = "sprites: { s: { rect: [0, 0, 16, 16] } }";
+    for bpp in [options::Bpp::Eight, options::Bpp::Four] {
+        let options = Options {
+            bpp,
+            palette_base: 16,
+            ..Options::default()
+        };
+        let compiled = compile_sheet(desc, bmp.clone(), &options).unwrap();
+        let sprite = &compiled.sprites["s"];
+        let entries = compiled.palette_entries();
+
+        // Read each pixel back from the bytes and palette as emitted.
+        let bytes = sprite.tile_bytes(&options);
+        for y in 0..16 {
+            for x in 0..16 {
+                let (tile, i) = (y / 8 * 2 + x / 8, y % 8 * 8 + x % 8);
+                let index = match sprite.bank {
+                    Some(bank) => match bytes[tile][i / 2] >> (i % 2 * 4) & 0xf {
+                        0 => 0,
+                        n => bank as usize * 16 + n as usize,
+                    },
+                    None => bytes[tile][i] as usize,
+                };
+                let source = bmp.buffer[y * 16 + x];
+                match index {
+                    0 => assert_eq!(source.a, 0),
+                    i => assert_eq!(
+                        entries[i - options.palette_base as usize],
+                        PaletteEntry::Color(source.rgb()),
+                        "{:?} pixel ({}, {})",
+                        bpp,
+                        x,
+                        y
+                    ),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn tags() {
+    let bmp = sheet(24, 8, |x, _| RGBA::new(255, x as u8 * 8, 0, 255));
+    let desc = "{ group: hud, sprites: { a: { rect: [0, 0, 8, 8], tags: [ui] }, \
+                b: { rect: [8, 0, 8, 8], tags: [ui, enemy] }, c: { rect: [16, 0, 8, 8] } } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &Options::default()).unwrap();
+    let mut out = String::new();
+    compiled.write_to(&mut out, &Options::default()).unwrap();
+    assert!(out.contains(
+        "/// Every uncompressed sprite tagged `ui`.\n\
+         pub const UI_SPRITES: &'static [&'static [[u8; 64]]] = &[hud::A, hud::B];\n\
+         /// The name of each sprite in `UI_SPRITES`.\n\
+         pub const UI_SPRITE_NAMES: &'static [&'static str] = &[\"a\", \"b\"];"
+    ));
+    assert!(out.contains("pub const ENEMY_SPRITES: &'static [&'static [[u8; 64]]] = &[hud::B];"));
+
+    let desc = "sprites: { a: { rect: [0, 0, 8, 8], tags: [ui] }, \
+                b: { rect: [8, 0, 8, 8], tags: [ui], bpp: 4 } }";
+    let compiled = compile_sheet(desc, bmp, &Options::default()).unwrap();
+    let err = compiled
+        .write_to(&mut String::new(), &Options::default())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Sprites a and b are both tagged ui, but one is 4bpp and the other 8bpp, so they can't \
+         share a list"
+    );
+
+    for tag in ["my-tag", "type", "r#ui"] {
+        let desc = format!(
+            "sprites: {{ a: {{ rect: [0, 0, 8, 8], tags: [{:?}] }} }}",
+            tag
+        );
+        let err = check_names(Path::new("hud.yml"), &serde_yaml::from_str(&desc).unwrap());
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            format!(
+                "hud.yml tags sprite a {:?}, but tags must be Rust identifiers",
+                tag
+            )
+        );
+    }
+}
+
+#[test]
+fn deny_warnings() {
+    let bmp = sheet(16, 8, |_, _| RGBA::new(255, 0, 0, 255));
+    let desc = "sprites: { a: { rect: [0, 0, 8, 8] }, b: { rect: [4, 0, 8, 8] } }";
+    let options = Options {
+        deny_warnings: true,
+        ..Options::default()
+    };
+    let compiled = compile_sheet(desc, bmp.clone(), &options).unwrap();
+    let err = emit(compiled, &options, String::new()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "1 warning denied by deny_warnings:\nSprites a and b have overlapping rects"
+    );
+
+    let compiled = compile_sheet(desc, bmp, &Options::default()).unwrap();
+    assert!(emit(compiled, &Options::default(), String::new()).is_ok());
+}
+
+#[test]
+fn bottom_left_origin() {
+    // Red on top, and green below.
+    let bmp = sheet(8, 24, |_, y| match y {
+        0..=7 => RGBA::new(255, 0, 0, 255),
+        _ => RGBA::new(0, 255, 0, 255),
+    });
+    let desc = "{ coordinate_origin: bottom_left, anchors: { top: [0, 16] }, \
+                sprites: { low: { rect: [0, 0, 8, 8] }, high: { anchor: top, rect: [0, 0, 8, 8] } } }";
+    let compiled = compile_sheet(desc, bmp.clone(), &Options::default()).unwrap();
+    let color =
+        |id: &str| compiled.index_color()(&compiled.sprites[id], compiled.sprites[id].tiles[0][0]);
+    assert_eq!(color("low"), Some(RGB::new(0, 255, 0)));
+    assert_eq!(color("high"), Some(RGB::new(255, 0, 0)));
+
+    let desc = "{ coordinate_origin: bottom_left, sprites: { s: { rect: [0, 20, 8, 8] } } }";
+    let err = compile_sheet(desc, bmp, &Options::default()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Sprite s rect (0, 20, 8, 8) extends past the edge of the 8x24 image"
+    );
+}
+
+#[test]
+fn palette_only() {
+    let bmp = sheet(8, 8, |x, _| RGBA::new(255, x as u8 % 2 * 255, 0, 255));
+    let desc = "sprites: { s: { rect: [0, 0, 8, 8] } }";
+    let options = Options {
+        palette_only: true,
+        ..Options::default()
+    };
+    let compiled = compile_sheet(desc, bmp, &options).unwrap();
+    let mut out = String::new();
+    compiled.write_to(&mut out, &options).unwrap();
+    assert!(out.ends_with(
+        "pub const PALETTE: &'static [Color] = &[\n    Color::from_rgb(0, 0, 0), // transparent\n    \
+         Color::from_rgb(31, 0, 0),\n    Color::from_rgb(31, 31, 0),\n];\n\n"
+    ));
+
+    let (mut header, mut source) = (String::new(), String::new());
+    compiled
+        .write_c_to("sheet", &options, &mut header, &mut source)
+        .unwrap();
+    assert!(header.contains("sheetPal[3]") && !header.contains("Tiles"));
+}
+
+#[test]
+fn debug_comments() {
+    let bmp = sheet(48, 16, |x, y| match (x % 24, y) {
+        (0..=7, _) | (_, 0..=7) => RGBA::new(0, 0, 0, 0),
+        _ => RGBA::new(255, (x % 4) as u8, 0, 255),
+    });
+    let options = Options {
+        debug_comments: true,
+        tile_align: NonZeroUsize::new(3),
+        ..Options::default()
+    };
+    // Trimming leaves two tiles of each 24x16 frame, from 8 pixels in and down.
+    let desc = "sprites: { hero: { rect: [0, 0, 48, 16], frames: 2, trim: true } }";
+    let compiled = compile_sheet(desc, bmp, &options).unwrap();
+    let mut out = String::new();
+    compiled.write_to(&mut out, &options).unwrap();
+    let comments = out
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("// "))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        comments,
+        [
+            "tile (0, 0) of frame 0 from sprite 'hero' at sheet (8, 8)",
+            "tile (1, 0) of frame 0 from sprite 'hero' at sheet (16, 8)",
+            "tile (0, 0) of frame 1 from sprite 'hero' at sheet (32, 8)",
+            "tile (1, 0) of frame 1 from sprite 'hero' at sheet (40, 8)",
+            "padding of sprite 'hero'",
+            "padding of sprite 'hero'",
+        ]
+    );
+}
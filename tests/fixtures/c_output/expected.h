@@ -0,0 +1,14 @@
+#ifndef SPRITES_H
+#define SPRITES_H
+
+#define spritesPalBase 0
+#define spritesPalLen 10
+extern const unsigned short spritesPal[5];
+
+#define bigTilesLen 256
+extern const unsigned char bigTiles[256];
+
+#define smallTilesLen 128
+extern const unsigned char smallTiles[128];
+
+#endif // SPRITES_H
@@ -0,0 +1,50 @@
+const _: &[u8] = include_bytes!("../tests/fixtures/compression/sheet.yml");
+const _: &[u8] = include_bytes!("../tests/fixtures/compression/sheet.png");
+use ::gba::mmio_types::Color;
+
+pub const PALETTE_BASE: usize = 0;
+pub const PALETTE: &'static [Color] = &[
+    Color::from_rgb(0, 0, 0), // transparent
+    Color::from_rgb(31, 0, 0),
+    Color::from_rgb(0, 31, 0),
+    Color::from_rgb(0, 0, 31),
+    Color::from_rgb(31, 31, 31),
+];
+
+/// Dimensions and OAM shape of a sprite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteMeta {
+    pub width: u16,
+    pub height: u16,
+    /// Attribute 0 bits 14-15: 0 for square, 1 for wide, 2 for tall.
+    pub shape: u16,
+    /// Attribute 1 bits 14-15.
+    pub size: u16,
+}
+
+/// Compressed for the BIOS `LZ77UnCompWram` and `LZ77UnCompVram` routines.
+pub const BIG: &'static [u32] = &[
+    0x00010010, 0xf001013c, 0xf001f001, 0x02015001, 0x01f0f302, 0x01f001f0, 0x03030150, 0x01f001f0,
+    0x5001f0cf, 0xf0040401, 0xf001f001, 0x00015001,
+];
+pub const BIG_COMPRESSED: bool = true;
+pub const BIG_DECOMPRESSED_SIZE: usize = 256;
+pub const BIG_META: SpriteMeta = SpriteMeta { width: 16, height: 16, shape: 0, size: 1 };
+/// Compressed for the BIOS `RLUnCompWram` and `RLUnCompVram` routines.
+pub const SMALL: &'static [u32] = &[
+    0x00008030, 0x00bd02bd,
+];
+pub const SMALL_COMPRESSED: bool = true;
+pub const SMALL_DECOMPRESSED_SIZE: usize = 128;
+pub const SMALL_META: SpriteMeta = SpriteMeta { width: 8, height: 16, shape: 2, size: 0 };
+
+/// Every uncompressed sprite, indexed by its `_ID`.
+pub const ALL_SPRITES: &'static [&'static [[u8; 64]]] = &[];
+/// The name of each sprite in `ALL_SPRITES`.
+pub const SPRITE_NAMES: &'static [&'static str] = &[];
+/// The tiles of the sprite in `ALL_SPRITES` named `name`, if there is one.
+pub fn sprite_by_name(name: &str) -> Option<&'static [[u8; 64]]> {
+    match name {
+        _ => None,
+    }
+}
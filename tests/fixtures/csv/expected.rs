@@ -0,0 +1,38 @@
+const _: &[u8] = include_bytes!("../tests/fixtures/csv/sheet.csv");
+const _: &[u8] = include_bytes!("../tests/fixtures/csv/sheet.png");
+use ::gba::mmio_types::Color;
+
+pub const PALETTE_BASE: usize = 0;
+pub const PALETTE: &'static [Color] = &[
+    Color::from_rgb(0, 0, 0), // transparent
+    Color::from_rgb(31, 0, 0),
+];
+
+/// Dimensions and OAM shape of a sprite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteMeta {
+    pub width: u16,
+    pub height: u16,
+    /// Attribute 0 bits 14-15: 0 for square, 1 for wide, 2 for tall.
+    pub shape: u16,
+    /// Attribute 1 bits 14-15.
+    pub size: u16,
+}
+
+pub const CHECKERS: &'static [[u8; 64]] = &[
+    [1, 0, 1, 0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0, 1, 1, 0, 1, 0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0, 1, 1, 0, 1, 0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0, 1, 1, 0, 1, 0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0, 1],
+];
+pub const CHECKERS_META: SpriteMeta = SpriteMeta { width: 8, height: 8, shape: 0, size: 0 };
+
+pub const CHECKERS_ID: usize = 0;
+/// Every uncompressed sprite, indexed by its `_ID`.
+pub const ALL_SPRITES: &'static [&'static [[u8; 64]]] = &[CHECKERS];
+/// The name of each sprite in `ALL_SPRITES`.
+pub const SPRITE_NAMES: &'static [&'static str] = &["checkers"];
+/// The tiles of the sprite in `ALL_SPRITES` named `name`, if there is one.
+pub fn sprite_by_name(name: &str) -> Option<&'static [[u8; 64]]> {
+    match name {
+        "checkers" => Some(CHECKERS),
+        _ => None,
+    }
+}
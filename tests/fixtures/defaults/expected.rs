@@ -0,0 +1,125 @@
+const _: &[u8] = include_bytes!("../tests/fixtures/defaults/_defaults.yml");
+const _: &[u8] = include_bytes!("../tests/fixtures/defaults/icons/_defaults.yml");
+const _: &[u8] = include_bytes!("../tests/fixtures/defaults/icons/gem.yml");
+const _: &[u8] = include_bytes!("../tests/fixtures/defaults/icons/gem.png");
+const _: &[u8] = include_bytes!("../tests/fixtures/defaults/panel.yml");
+const _: &[u8] = include_bytes!("../tests/fixtures/defaults/panel.png");
+use ::gba::mmio_types::Color;
+
+pub const PALETTE_BASE: usize = 0;
+pub const PALETTE: &'static [Color] = &[
+    Color::from_rgb(0, 0, 0), // transparent
+    Color::from_rgb(0, 0, 31),
+    Color::from_rgb(0, 31, 0),
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // transparent
+    Color::from_rgb(0, 31, 0),
+    Color::from_rgb(31, 0, 0),
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+];
+
+/// Each palette bank in `PALETTE`, from OBJ palette bank 1, for uploading one at a time.
+pub const BANK_PALETTES: [[Color; 16]; 1] = [
+    [
+        Color::from_rgb(0, 0, 0), // transparent
+        Color::from_rgb(0, 31, 0),
+        Color::from_rgb(31, 0, 0),
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+    ],
+];
+
+/// Dimensions and OAM shape of a sprite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteMeta {
+    pub width: u16,
+    pub height: u16,
+    /// Attribute 0 bits 14-15: 0 for square, 1 for wide, 2 for tall.
+    pub shape: u16,
+    /// Attribute 1 bits 14-15.
+    pub size: u16,
+}
+
+pub const PANEL: &'static [[u8; 32]] = &[
+    [33, 33, 33, 33, 18, 18, 18, 18, 33, 33, 33, 33, 18, 18, 18, 18, 33, 33, 33, 33, 18, 18, 18, 18, 33, 33, 33, 33, 18, 18, 18, 18],
+];
+pub const PANEL_META: SpriteMeta = SpriteMeta { width: 8, height: 8, shape: 0, size: 0 };
+pub const PANEL_PALBANK: u16 = 1;
+pub const PANEL_PALETTE: [Color; 16] = BANK_PALETTES[0];
+/// Bit `i` is set if the sprite's tiles use index `i` of its palette bank.
+pub const PANEL_COLORS_USED: u16 = 0b0000000000000110;
+
+pub mod icons {
+    use super::*;
+
+    pub const GEM: &'static [[u8; 64]] = &[
+        [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+    ];
+    pub const GEM_META: SpriteMeta = SpriteMeta { width: 8, height: 8, shape: 0, size: 0 };
+    /// Attribute 2 bits 10-11.
+    pub const GEM_PRIORITY: u16 = 1;
+}
+
+pub const GEM_ID: usize = 0;
+/// Every uncompressed sprite, indexed by its `_ID`.
+pub const ALL_SPRITES: &'static [&'static [[u8; 64]]] = &[icons::GEM];
+/// The name of each sprite in `ALL_SPRITES`.
+pub const SPRITE_NAMES: &'static [&'static str] = &["gem"];
+/// The tiles of the sprite in `ALL_SPRITES` named `name`, if there is one.
+pub fn sprite_by_name(name: &str) -> Option<&'static [[u8; 64]]> {
+    match name {
+        "gem" => Some(icons::GEM),
+        _ => None,
+    }
+}
+pub const PANEL_ID: usize = 0;
+/// Every uncompressed 4bpp sprite, indexed by its `_ID`.
+pub const ALL_SPRITES_4BPP: &'static [&'static [[u8; 32]]] = &[PANEL];
+/// The name of each sprite in `ALL_SPRITES_4BPP`.
+pub const SPRITE_NAMES_4BPP: &'static [&'static str] = &["panel"];
+/// The tiles of the sprite in `ALL_SPRITES_4BPP` named `name`, if there is one.
+pub fn sprite_by_name_4bpp(name: &str) -> Option<&'static [[u8; 32]]> {
+    match name {
+        "panel" => Some(PANEL),
+        _ => None,
+    }
+}
+
+/// The sprites using each palette bank, starting from OBJ palette bank 1.
+/// Bank `i`'s colors are `BANK_PALETTES[i]`.
+pub const BANKS: &'static [&'static [&'static str]] = &[&["panel"]];
@@ -0,0 +1,110 @@
+const _: &[u8] = include_bytes!("../tests/fixtures/groups/enemy.yml");
+const _: &[u8] = include_bytes!("../tests/fixtures/groups/enemy.png");
+const _: &[u8] = include_bytes!("../tests/fixtures/groups/hero.yml");
+const _: &[u8] = include_bytes!("../tests/fixtures/groups/hero.png");
+const _: &[u8] = include_bytes!("../tests/fixtures/groups/ui.yml");
+const _: &[u8] = include_bytes!("../tests/fixtures/groups/ui.png");
+use ::gba::mmio_types::Color;
+
+pub const PALETTE_BASE: usize = 0;
+pub const PALETTE: &'static [Color] = &[
+    Color::from_rgb(0, 0, 0), // transparent
+    Color::from_rgb(0, 31, 0),
+    Color::from_rgb(31, 0, 0),
+];
+
+/// Dimensions and OAM shape of a sprite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteMeta {
+    pub width: u16,
+    pub height: u16,
+    /// Attribute 0 bits 14-15: 0 for square, 1 for wide, 2 for tall.
+    pub shape: u16,
+    /// Attribute 1 bits 14-15.
+    pub size: u16,
+}
+
+pub const CURSOR: &'static [[u8; 64]] = &[
+    [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+];
+pub const CURSOR_META: SpriteMeta = SpriteMeta { width: 8, height: 8, shape: 0, size: 0 };
+/// Uploads `cursor` to OBJ VRAM at OBJ tile index `tile_base`, and `PALETTE` to OBJ palette RAM at `PALETTE_BASE`.
+///
+/// # Safety
+/// Must run on the GBA, with `tile_base` leaving room for all of `CURSOR`.
+pub unsafe fn load_cursor(tile_base: usize) {
+    upload_obj_tiles(CURSOR, tile_base);
+    upload_obj_palette(PALETTE, PALETTE_BASE);
+}
+
+pub mod characters {
+    use super::*;
+
+    pub const ENEMY: &'static [[u8; 64]] = &[
+        [2, 0, 2, 0, 2, 0, 2, 0, 0, 2, 0, 2, 0, 2, 0, 2, 2, 0, 2, 0, 2, 0, 2, 0, 0, 2, 0, 2, 0, 2, 0, 2, 2, 0, 2, 0, 2, 0, 2, 0, 0, 2, 0, 2, 0, 2, 0, 2, 2, 0, 2, 0, 2, 0, 2, 0, 0, 2, 0, 2, 0, 2, 0, 2],
+    ];
+    pub const ENEMY_META: SpriteMeta = SpriteMeta { width: 8, height: 8, shape: 0, size: 0 };
+    /// Uploads `enemy` to OBJ VRAM at OBJ tile index `tile_base`, and `PALETTE` to OBJ palette RAM at `PALETTE_BASE`.
+    ///
+    /// # Safety
+    /// Must run on the GBA, with `tile_base` leaving room for all of `ENEMY`.
+    pub unsafe fn load_enemy(tile_base: usize) {
+        upload_obj_tiles(ENEMY, tile_base);
+        upload_obj_palette(PALETTE, PALETTE_BASE);
+    }
+    /// The same tiles as `ENEMY`.
+    pub const HERO: &'static [[u8; 64]] = ENEMY;
+    pub const HERO_META: SpriteMeta = SpriteMeta { width: 8, height: 8, shape: 0, size: 0 };
+    /// Uploads `hero` to OBJ VRAM at OBJ tile index `tile_base`, and `PALETTE` to OBJ palette RAM at `PALETTE_BASE`.
+    ///
+    /// # Safety
+    /// Must run on the GBA, with `tile_base` leaving room for all of `HERO`.
+    pub unsafe fn load_hero(tile_base: usize) {
+        upload_obj_tiles(HERO, tile_base);
+        upload_obj_palette(PALETTE, PALETTE_BASE);
+    }
+}
+
+pub const CURSOR_ID: usize = 0;
+pub const ENEMY_ID: usize = 1;
+pub const HERO_ID: usize = 2;
+/// Every uncompressed sprite, indexed by its `_ID`.
+pub const ALL_SPRITES: &'static [&'static [[u8; 64]]] = &[CURSOR, characters::ENEMY, characters::HERO];
+/// The name of each sprite in `ALL_SPRITES`.
+pub const SPRITE_NAMES: &'static [&'static str] = &["cursor", "enemy", "hero"];
+/// The tiles of the sprite in `ALL_SPRITES` named `name`, if there is one.
+pub fn sprite_by_name(name: &str) -> Option<&'static [[u8; 64]]> {
+    match name {
+        "cursor" => Some(CURSOR),
+        "enemy" => Some(characters::ENEMY),
+        "hero" => Some(characters::HERO),
+        _ => None,
+    }
+}
+
+/// Copies `tiles` of either color depth to OBJ VRAM, starting at OBJ tile index `tile_base`
+/// (in 32 byte units).
+///
+/// # Safety
+/// Must run on the GBA, with `tile_base` leaving room for all of `tiles`.
+pub unsafe fn upload_obj_tiles<const N: usize>(tiles: &[[u8; N]], tile_base: usize) {
+    // VRAM doesn't support byte writes, so copy a halfword at a time.
+    let mut dst = (0x0601_0000 + tile_base * 32) as *mut u16;
+    for tile in tiles {
+        for pair in tile.chunks_exact(2) {
+            dst.write_volatile(u16::from_le_bytes([pair[0], pair[1]]));
+            dst = dst.add(1);
+        }
+    }
+}
+
+/// Copies `palette` to OBJ palette RAM, starting at index `base`.
+///
+/// # Safety
+/// Must run on the GBA, with `base` leaving room for all of `palette`.
+pub unsafe fn upload_obj_palette(palette: &[Color], base: usize) {
+    let dst = (0x0500_0200 as *mut Color).add(base);
+    for (i, &color) in palette.iter().enumerate() {
+        dst.add(i).write_volatile(color);
+    }
+}
@@ -0,0 +1,38 @@
+const _: &[u8] = include_bytes!("../tests/fixtures/hex_tiles/sheet.yml");
+const _: &[u8] = include_bytes!("../tests/fixtures/hex_tiles/sheet.png");
+use ::gba::mmio_types::Color;
+
+pub const PALETTE_BASE: usize = 0;
+pub const PALETTE: &'static [Color] = &[
+    Color::from_rgb(0, 0, 0), // transparent
+    Color::from_rgb(31, 0, 0),
+];
+
+/// Dimensions and OAM shape of a sprite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteMeta {
+    pub width: u16,
+    pub height: u16,
+    /// Attribute 0 bits 14-15: 0 for square, 1 for wide, 2 for tall.
+    pub shape: u16,
+    /// Attribute 1 bits 14-15.
+    pub size: u16,
+}
+
+pub const CHECKER: &'static [[u32; 16]] = &[
+    [0x00010001, 0x00010001, 0x01000100, 0x01000100, 0x00010001, 0x00010001, 0x01000100, 0x01000100, 0x00010001, 0x00010001, 0x01000100, 0x01000100, 0x00010001, 0x00010001, 0x01000100, 0x01000100],
+];
+pub const CHECKER_META: SpriteMeta = SpriteMeta { width: 8, height: 8, shape: 0, size: 0 };
+
+pub const CHECKER_ID: usize = 0;
+/// Every uncompressed sprite, indexed by its `_ID`.
+pub const ALL_SPRITES: &'static [&'static [[u32; 16]]] = &[CHECKER];
+/// The name of each sprite in `ALL_SPRITES`.
+pub const SPRITE_NAMES: &'static [&'static str] = &["checker"];
+/// The tiles of the sprite in `ALL_SPRITES` named `name`, if there is one.
+pub fn sprite_by_name(name: &str) -> Option<&'static [[u32; 16]]> {
+    match name {
+        "checker" => Some(CHECKER),
+        _ => None,
+    }
+}
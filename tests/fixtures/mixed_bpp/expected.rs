@@ -0,0 +1,175 @@
+const _: &[u8] = include_bytes!("../tests/fixtures/mixed_bpp/sheet.yml");
+const _: &[u8] = include_bytes!("../tests/fixtures/mixed_bpp/sheet.png");
+use ::gba::mmio_types::Color;
+
+pub const PALETTE_BASE: usize = 0;
+pub const PALETTE: &'static [Color] = &[
+    Color::from_rgb(0, 0, 0), // transparent
+    Color::from_rgb(31, 0, 0),
+    Color::from_rgb(2, 1, 31),
+    Color::from_rgb(4, 2, 31),
+    Color::from_rgb(6, 3, 31),
+    Color::from_rgb(8, 4, 31),
+    Color::from_rgb(10, 5, 31),
+    Color::from_rgb(12, 6, 31),
+    Color::from_rgb(14, 7, 31),
+    Color::from_rgb(16, 8, 31),
+    Color::from_rgb(18, 9, 31),
+    Color::from_rgb(20, 10, 31),
+    Color::from_rgb(22, 11, 31),
+    Color::from_rgb(24, 12, 31),
+    Color::from_rgb(26, 13, 31),
+    Color::from_rgb(28, 14, 31),
+    Color::from_rgb(0, 0, 0), // transparent
+    Color::from_rgb(0, 31, 0),
+    Color::from_rgb(31, 0, 0),
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+    Color::from_rgb(0, 0, 0), // unused
+];
+
+/// Each palette bank in `PALETTE`, from OBJ palette bank 1, for uploading one at a time.
+pub const BANK_PALETTES: [[Color; 16]; 1] = [
+    [
+        Color::from_rgb(0, 0, 0), // transparent
+        Color::from_rgb(0, 31, 0),
+        Color::from_rgb(31, 0, 0),
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+        Color::from_rgb(0, 0, 0), // unused
+    ],
+];
+
+/// Dimensions and OAM shape of a sprite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteMeta {
+    pub width: u16,
+    pub height: u16,
+    /// Attribute 0 bits 14-15: 0 for square, 1 for wide, 2 for tall.
+    pub shape: u16,
+    /// Attribute 1 bits 14-15.
+    pub size: u16,
+}
+
+pub const B: &'static [[u8; 64]] = &[
+    [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 1, 2, 3, 4],
+];
+pub const B_META: SpriteMeta = SpriteMeta { width: 8, height: 8, shape: 0, size: 0 };
+/// Uploads `b` to OBJ VRAM at OBJ tile index `tile_base`, and `PALETTE` to OBJ palette RAM at `PALETTE_BASE`.
+///
+/// # Safety
+/// Must run on the GBA, with `tile_base` leaving room for all of `B`.
+pub unsafe fn load_b(tile_base: usize) {
+    upload_obj_tiles(B, tile_base);
+    upload_obj_palette(PALETTE, PALETTE_BASE);
+}
+pub const A: &'static [[u8; 32]] = &[
+    [33, 33, 33, 33, 18, 18, 18, 18, 33, 33, 33, 33, 18, 18, 18, 18, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+];
+pub const A_META: SpriteMeta = SpriteMeta { width: 8, height: 8, shape: 0, size: 0 };
+pub const A_PALBANK: u16 = 1;
+pub const A_PALETTE: [Color; 16] = BANK_PALETTES[0];
+/// Bit `i` is set if the sprite's tiles use index `i` of its palette bank.
+pub const A_COLORS_USED: u16 = 0b0000000000000111;
+/// Uploads `a` to OBJ VRAM at OBJ tile index `tile_base`, and its palette bank to OBJ palette bank `A_PALBANK`.
+///
+/// # Safety
+/// Must run on the GBA, with `tile_base` leaving room for all of `A`.
+pub unsafe fn load_a(tile_base: usize) {
+    upload_obj_tiles(A, tile_base);
+    upload_obj_palette(&A_PALETTE, A_PALBANK as usize * 16);
+}
+pub const C: &'static [[u8; 32]] = &[
+    [2, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0],
+];
+pub const C_META: SpriteMeta = SpriteMeta { width: 8, height: 8, shape: 0, size: 0 };
+pub const C_PALBANK: u16 = 1;
+pub const C_PALETTE: [Color; 16] = BANK_PALETTES[0];
+/// Bit `i` is set if the sprite's tiles use index `i` of its palette bank.
+pub const C_COLORS_USED: u16 = 0b0000000000000101;
+/// Uploads `c` to OBJ VRAM at OBJ tile index `tile_base`, and its palette bank to OBJ palette bank `C_PALBANK`.
+///
+/// # Safety
+/// Must run on the GBA, with `tile_base` leaving room for all of `C`.
+pub unsafe fn load_c(tile_base: usize) {
+    upload_obj_tiles(C, tile_base);
+    upload_obj_palette(&C_PALETTE, C_PALBANK as usize * 16);
+}
+
+pub const A_ID: usize = 0;
+pub const C_ID: usize = 1;
+/// Every uncompressed sprite, indexed by its `_ID`.
+pub const ALL_SPRITES: &'static [&'static [[u8; 32]]] = &[A, C];
+/// The name of each sprite in `ALL_SPRITES`.
+pub const SPRITE_NAMES: &'static [&'static str] = &["a", "c"];
+/// The tiles of the sprite in `ALL_SPRITES` named `name`, if there is one.
+pub fn sprite_by_name(name: &str) -> Option<&'static [[u8; 32]]> {
+    match name {
+        "a" => Some(A),
+        "c" => Some(C),
+        _ => None,
+    }
+}
+pub const B_ID: usize = 0;
+/// Every uncompressed 8bpp sprite, indexed by its `_ID`.
+pub const ALL_SPRITES_8BPP: &'static [&'static [[u8; 64]]] = &[B];
+/// The name of each sprite in `ALL_SPRITES_8BPP`.
+pub const SPRITE_NAMES_8BPP: &'static [&'static str] = &["b"];
+/// The tiles of the sprite in `ALL_SPRITES_8BPP` named `name`, if there is one.
+pub fn sprite_by_name_8bpp(name: &str) -> Option<&'static [[u8; 64]]> {
+    match name {
+        "b" => Some(B),
+        _ => None,
+    }
+}
+
+/// The sprites using each palette bank, starting from OBJ palette bank 1.
+/// Bank `i`'s colors are `BANK_PALETTES[i]`.
+pub const BANKS: &'static [&'static [&'static str]] = &[&["a", "c"]];
+
+/// Copies `tiles` of either color depth to OBJ VRAM, starting at OBJ tile index `tile_base`
+/// (in 32 byte units).
+///
+/// # Safety
+/// Must run on the GBA, with `tile_base` leaving room for all of `tiles`.
+pub unsafe fn upload_obj_tiles<const N: usize>(tiles: &[[u8; N]], tile_base: usize) {
+    // VRAM doesn't support byte writes, so copy a halfword at a time.
+    let mut dst = (0x0601_0000 + tile_base * 32) as *mut u16;
+    for tile in tiles {
+        for pair in tile.chunks_exact(2) {
+            dst.write_volatile(u16::from_le_bytes([pair[0], pair[1]]));
+            dst = dst.add(1);
+        }
+    }
+}
+
+/// Copies `palette` to OBJ palette RAM, starting at index `base`.
+///
+/// # Safety
+/// Must run on the GBA, with `base` leaving room for all of `palette`.
+pub unsafe fn upload_obj_palette(palette: &[Color], base: usize) {
+    let dst = (0x0500_0200 as *mut Color).add(base);
+    for (i, &color) in palette.iter().enumerate() {
+        dst.add(i).write_volatile(color);
+    }
+}
@@ -0,0 +1,44 @@
+const _: &[u8] = include_bytes!("../tests/fixtures/mode3/sheet.yml");
+const _: &[u8] = include_bytes!("../tests/fixtures/mode3/sheet.png");
+use ::gba::mmio_types::Color;
+
+pub const PALETTE_BASE: usize = 0;
+pub const PALETTE: &'static [Color] = &[
+    Color::from_rgb(0, 0, 0), // transparent
+    Color::from_rgb(31, 31, 0),
+];
+
+/// Dimensions and OAM shape of a sprite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteMeta {
+    pub width: u16,
+    pub height: u16,
+    /// Attribute 0 bits 14-15: 0 for square, 1 for wide, 2 for tall.
+    pub shape: u16,
+    /// Attribute 1 bits 14-15.
+    pub size: u16,
+}
+
+pub const BALL: &'static [[u8; 64]] = &[
+    [0, 1, 0, 1, 0, 1, 0, 1, 1, 0, 1, 0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0, 1, 1, 0, 1, 0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0, 1, 1, 0, 1, 0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0, 1, 1, 0, 1, 0, 1, 0, 1, 0],
+];
+pub const BALL_META: SpriteMeta = SpriteMeta { width: 8, height: 8, shape: 0, size: 0 };
+/// A bitmap for Mode 3 of 8x2 pixels, as BGR555 colors row by row.
+pub const TITLE: &'static [u16] = &[
+    0x001f, 0x03e0, 0x7c00, 0x7fff, 0x001f, 0x03e0, 0x7c00, 0x7fff, 0x001f, 0x03e0, 0x7c00, 0x7fff, 0x001f, 0x03e0, 0x7c00, 0x7fff,
+];
+pub const TITLE_WIDTH: usize = 8;
+pub const TITLE_HEIGHT: usize = 2;
+
+pub const BALL_ID: usize = 0;
+/// Every uncompressed sprite, indexed by its `_ID`.
+pub const ALL_SPRITES: &'static [&'static [[u8; 64]]] = &[BALL];
+/// The name of each sprite in `ALL_SPRITES`.
+pub const SPRITE_NAMES: &'static [&'static str] = &["ball"];
+/// The tiles of the sprite in `ALL_SPRITES` named `name`, if there is one.
+pub fn sprite_by_name(name: &str) -> Option<&'static [[u8; 64]]> {
+    match name {
+        "ball" => Some(BALL),
+        _ => None,
+    }
+}
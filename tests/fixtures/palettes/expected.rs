@@ -0,0 +1,43 @@
+const _: &[u8] = include_bytes!("../tests/fixtures/palettes/sheet.yml");
+const _: &[u8] = include_bytes!("../tests/fixtures/palettes/ui.palette");
+const _: &[u8] = include_bytes!("../tests/fixtures/palettes/hud.palette");
+const _: &[u8] = include_bytes!("../tests/fixtures/palettes/sheet.png");
+use ::gba::mmio_types::Color;
+
+pub const PALETTE_BASE: usize = 0;
+pub const PALETTE: &'static [Color] = &[
+    Color::from_rgb(0, 0, 0), // transparent
+    Color::from_rgb(31, 31, 31),
+    Color::from_rgb(0, 0, 0),
+    Color::from_rgb(0, 0, 31),
+    Color::from_rgb(31, 0, 0),
+];
+
+/// Dimensions and OAM shape of a sprite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteMeta {
+    pub width: u16,
+    pub height: u16,
+    /// Attribute 0 bits 14-15: 0 for square, 1 for wide, 2 for tall.
+    pub shape: u16,
+    /// Attribute 1 bits 14-15.
+    pub size: u16,
+}
+
+pub const S: &'static [[u8; 64]] = &[
+    [4, 4, 1, 1, 3, 3, 4, 4, 4, 4, 1, 1, 3, 3, 4, 4, 4, 4, 1, 1, 3, 3, 4, 4, 4, 4, 1, 1, 3, 3, 4, 4, 4, 4, 1, 1, 3, 3, 4, 4, 4, 4, 1, 1, 3, 3, 4, 4, 4, 4, 1, 1, 3, 3, 4, 4, 4, 4, 1, 1, 3, 3, 4, 4],
+];
+pub const S_META: SpriteMeta = SpriteMeta { width: 8, height: 8, shape: 0, size: 0 };
+
+pub const S_ID: usize = 0;
+/// Every uncompressed sprite, indexed by its `_ID`.
+pub const ALL_SPRITES: &'static [&'static [[u8; 64]]] = &[S];
+/// The name of each sprite in `ALL_SPRITES`.
+pub const SPRITE_NAMES: &'static [&'static str] = &["s"];
+/// The tiles of the sprite in `ALL_SPRITES` named `name`, if there is one.
+pub fn sprite_by_name(name: &str) -> Option<&'static [[u8; 64]]> {
+    match name {
+        "s" => Some(S),
+        _ => None,
+    }
+}